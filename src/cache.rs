@@ -0,0 +1,183 @@
+//! On-disk HTTP cache for plain downloads like the awesome list. Keeps the
+//! body with its ETag / Last-Modified and revalidates with conditional
+//! requests, so an unchanged list costs a 304 and no parsing.
+use crate::downloader::{self, Config};
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// How the returned body relates to the previous run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Freshness {
+    /// First download, or the content differs from the cached copy.
+    Changed,
+    /// The server answered 304 or sent the cached content again.
+    Unchanged,
+    /// The server was unreachable, the cached copy is returned as is.
+    Offline,
+}
+
+#[derive(Clone, Debug)]
+pub struct CachedDownload {
+    body: String,
+    freshness: Freshness,
+}
+
+impl CachedDownload {
+    pub fn body(&self) -> &String {
+        &self.body
+    }
+
+    pub fn into_body(self) -> String {
+        self.body
+    }
+
+    pub fn freshness(&self) -> Freshness {
+        self.freshness
+    }
+
+    pub fn changed(&self) -> bool {
+        self.freshness == Freshness::Changed
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl Entry {
+    fn into_download(self, freshness: Freshness) -> CachedDownload {
+        CachedDownload {
+            body: self.body,
+            freshness,
+        }
+    }
+}
+
+/// Directory with one JSON entry per url.
+#[derive(Clone, Debug)]
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        HttpCache { dir: dir.into() }
+    }
+
+    /// Downloads `url`, or takes it from the cache if the server says it is
+    /// unchanged or can't be reached. Errors only without a cached copy.
+    pub async fn download(&self, config: &Config, url: &str) -> Result<CachedDownload, Error> {
+        let path = self.entry_path(url);
+        let cached = load_entry(&path).filter(|entry| entry.url == url);
+
+        let mut headers = vec![];
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                headers.push(("If-None-Match".to_string(), etag.clone()));
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                headers.push(("If-Modified-Since".to_string(), last_modified.clone()));
+            }
+        }
+
+        let res = match downloader::get_with_headers(config, url, headers).await {
+            Ok(res) => res,
+            Err(e) => {
+                tracing::warn!(url, error = %e, cached = cached.is_some(), "download failed");
+                return cached
+                    .map(|entry| entry.into_download(Freshness::Offline))
+                    .ok_or(e);
+            }
+        };
+        tracing::debug!(
+            url,
+            status = res.status,
+            cached = cached.is_some(),
+            "cache revalidated"
+        );
+        let cached = match (res.status, cached) {
+            (200, cached) => cached,
+            (304, Some(entry)) => return Ok(entry.into_download(Freshness::Unchanged)),
+            (status, Some(entry)) if status >= 500 => {
+                return Ok(entry.into_download(Freshness::Offline))
+            }
+            (status, _) => {
+                return Err(Error::Http {
+                    status,
+                    url: url.to_string(),
+                })
+            }
+        };
+
+        let freshness = match cached {
+            Some(entry) if entry.body == res.body => Freshness::Unchanged,
+            _ => Freshness::Changed,
+        };
+        let entry = Entry {
+            url: url.to_string(),
+            etag: res.response_header("ETag").map(str::to_string),
+            last_modified: res.response_header("Last-Modified").map(str::to_string),
+            body: res.body,
+        };
+        save_entry(&self.dir, &path, &entry)?;
+        Ok(entry.into_download(freshness))
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let name: String = url
+            .split("://")
+            .last()
+            .unwrap_or(url)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+/// A missing or unreadable entry is a cache miss.
+fn load_entry(path: &Path) -> Option<Entry> {
+    let file = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&file).ok()
+}
+
+/// Writes to a temporary file first, an interrupted run can't leave a
+/// truncated entry behind.
+fn save_entry(dir: &Path, path: &Path, entry: &Entry) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)?;
+    let tmp = path.with_extension("json.tmp");
+    let entry = serde_json::to_string(entry).map_err(std::io::Error::from)?;
+    std::fs::write(&tmp, entry)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_path() {
+        let cache = HttpCache::new("/tmp/cache");
+
+        assert_eq!(
+            cache.entry_path(
+                "https://raw.githubusercontent.com/bnb/awesome-developer-streams/master/README.md"
+            ),
+            Path::new(
+                "/tmp/cache/raw.githubusercontent.com_bnb_awesome-developer-streams_master_README.md.json"
+            )
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Record-and-replay of the HTTP traffic of the downloader. A recorded
+//! cassette is a JSON file with every request and response of a crawl, with
+//! credentials scrubbed, that can be replayed offline.
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub const SCRUBBED: &str = "[scrubbed]";
+const SCRUBBED_HEADERS: [&str; 2] = ["Authorization", "Client-ID"];
+const SCRUBBED_QUERY_KEYS: [&str; 2] = ["client_id", "client_secret"];
+const SCRUBBED_BODY_KEYS: [&str; 2] = ["access_token", "refresh_token"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Interaction {
+    pub method: String,
+    pub url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub body: String,
+    /// `body` is hex encoded, for images and other binary responses.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub binary: bool,
+}
+
+impl Interaction {
+    pub fn response_header(&self, name: &str) -> Option<&str> {
+        self.response_headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Response body as recorded, decoded if it is binary.
+    pub fn body_bytes(&self) -> Result<Vec<u8>, Error> {
+        if !self.binary {
+            return Ok(self.body.clone().into_bytes());
+        }
+        hex::decode(&self.body).map_err(|e| Error::transport(&self.url, e))
+    }
+
+    fn scrubbed(mut self) -> Self {
+        self.url = scrub_url(&self.url);
+        for (name, value) in self.request_headers.iter_mut() {
+            if SCRUBBED_HEADERS
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case(name))
+            {
+                *value = SCRUBBED.to_string();
+            }
+        }
+        if let Ok(serde_json::Value::Object(mut body)) = serde_json::from_str(&self.body) {
+            let mut changed = false;
+            for key in SCRUBBED_BODY_KEYS.iter() {
+                if let Some(value) = body.get_mut(*key) {
+                    *value = SCRUBBED.into();
+                    changed = true;
+                }
+            }
+            if changed {
+                self.body = serde_json::Value::Object(body).to_string();
+            }
+        }
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&file)
+            .map_err(|e| Error::decode(path.as_ref().display().to_string(), &file, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let cassette = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+        std::fs::write(path, cassette)?;
+        Ok(())
+    }
+
+    pub fn interactions(&self) -> &[Interaction] {
+        &self.interactions
+    }
+}
+
+/// Collects the interactions of all requests made with a config, shared
+/// between clones so concurrent requests end up in the same cassette.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    pub(crate) fn record(&self, interaction: Interaction) {
+        self.cassette
+            .lock()
+            .unwrap()
+            .interactions
+            .push(interaction.scrubbed());
+    }
+
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.cassette().save(path)
+    }
+}
+
+/// Serves the responses of a cassette. Requests are matched by method and
+/// url, a url requested several times gets its responses in recorded order.
+#[derive(Clone, Debug)]
+pub struct Player {
+    remaining: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl Player {
+    pub fn new(cassette: Cassette) -> Self {
+        Player {
+            remaining: Arc::new(Mutex::new(cassette.interactions)),
+        }
+    }
+
+    pub(crate) fn play(&self, method: &str, url: &str) -> Result<Interaction, Error> {
+        let url = scrub_url(url);
+        let mut remaining = self.remaining.lock().unwrap();
+        match remaining
+            .iter()
+            .position(|i| i.method == method && i.url == url)
+        {
+            Some(pos) => Ok(remaining.remove(pos)),
+            None => Err(Error::transport(
+                url,
+                format!("no recorded response for {}", method),
+            )),
+        }
+    }
+}
+
+/// `url` with credentials in the query replaced by [`SCRUBBED`].
+pub(crate) fn scrub_url(url: &str) -> String {
+    let (base, query) = match url.find('?') {
+        Some(pos) => (&url[..pos], &url[pos + 1..]),
+        None => return url.to_string(),
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            if SCRUBBED_QUERY_KEYS.contains(&key) {
+                format!("{}={}", key, SCRUBBED)
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect();
+    format!("{}?{}", base, query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub_interaction() {
+        let interaction = Interaction {
+            method: "POST".to_string(),
+            url: "https://id.twitch.tv/oauth2/token?client_id=id&client_secret=secret&grant_type=client_credentials".to_string(),
+            request_headers: vec![
+                ("Authorization".to_string(), "Bearer token".to_string()),
+                ("Client-ID".to_string(), "id".to_string()),
+            ],
+            status: 200,
+            response_headers: vec![],
+            body: r#"{"access_token":"token","expires_in":100}"#.to_string(),
+            binary: false,
+        };
+
+        let scrubbed = interaction.scrubbed();
+
+        assert_eq!(
+            scrubbed.url,
+            "https://id.twitch.tv/oauth2/token?client_id=[scrubbed]&client_secret=[scrubbed]&grant_type=client_credentials"
+        );
+        assert!(scrubbed.request_headers.iter().all(|(_, v)| v == SCRUBBED));
+        assert!(!scrubbed.body.contains("\"token\""));
+        assert!(scrubbed.body.contains("expires_in"));
+    }
+
+    #[test]
+    fn test_play_in_recorded_order() {
+        let interaction = |status| Interaction {
+            method: "GET".to_string(),
+            url: "https://api.twitch.tv/helix/users?login=s_1".to_string(),
+            request_headers: vec![],
+            status,
+            response_headers: vec![],
+            body: String::new(),
+            binary: false,
+        };
+        let player = Player::new(Cassette {
+            interactions: vec![interaction(429), interaction(200)],
+        });
+
+        let url = "https://api.twitch.tv/helix/users?login=s_1";
+        assert_eq!(player.play("GET", url).unwrap().status, 429);
+        assert_eq!(player.play("GET", url).unwrap().status, 200);
+        assert!(player.play("GET", url).is_err());
+    }
+}
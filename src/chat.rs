@@ -0,0 +1,524 @@
+//! Twitch chat over IRC (TMI), used to measure how active the chat of a
+//! live stream is: message rate, unique chatters and emote usage.
+use crate::AsyncError;
+use async_std::{future, io::BufReader, net::TcpStream, prelude::*, task};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+const TMI_ADDRESS: &str = "irc.chat.twitch.tv:6667";
+/// Nicks starting with `justinfan` log in anonymously, read-only.
+const ANONYMOUS_NICK: &str = "justinfan12345";
+const MAX_RECONNECTS: u32 = 5;
+/// Unverified accounts may join 20 channels per 10 seconds.
+const JOIN_CHUNK_SIZE: usize = 20;
+const JOIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How to log in to Twitch chat.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Login {
+    /// Read-only, no account needed.
+    Anonymous,
+    /// `token` is an OAuth user token with the `chat:read` scope.
+    OAuth { nick: String, token: String },
+}
+
+/// Server address, login and reconnect behavior of the chat client.
+#[derive(Clone, Debug)]
+pub struct ChatConfig {
+    address: String,
+    login: Login,
+    max_reconnects: u32,
+    reconnect_delay: Duration,
+    join_interval: Duration,
+}
+
+impl ChatConfig {
+    pub fn anonymous() -> Self {
+        ChatConfig {
+            address: TMI_ADDRESS.to_string(),
+            login: Login::Anonymous,
+            max_reconnects: MAX_RECONNECTS,
+            reconnect_delay: Duration::from_secs(1),
+            join_interval: JOIN_INTERVAL,
+        }
+    }
+
+    pub fn with_address(mut self, address: impl Into<String>) -> Self {
+        self.address = address.into();
+        self
+    }
+
+    pub fn with_login(mut self, nick: impl Into<String>, token: impl Into<String>) -> Self {
+        self.login = Login::OAuth {
+            nick: nick.into(),
+            token: token.into(),
+        };
+        self
+    }
+
+    /// Reconnects after the server closed the connection or sent RECONNECT.
+    pub fn with_max_reconnects(mut self, max_reconnects: u32) -> Self {
+        self.max_reconnects = max_reconnects;
+        self
+    }
+
+    pub fn with_reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// Pause between JOINs of 20 channels, to stay within the join rate limit.
+    pub fn with_join_interval(mut self, join_interval: Duration) -> Self {
+        self.join_interval = join_interval;
+        self
+    }
+}
+
+/// One IRC line, with the IRCv3 tags Twitch sends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    tags: Vec<(String, String)>,
+    prefix: Option<String>,
+    command: String,
+    params: Vec<String>,
+}
+
+impl Message {
+    /// Parses a line like
+    /// `@badges=;emotes=25:0-4 :nick!nick@nick.tmi.twitch.tv PRIVMSG #channel :Kappa`.
+    pub fn parse(line: &str) -> Result<Message, AsyncError> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        let mut tags = vec![];
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (raw_tags, tail) = split_word(tagged);
+            tags = raw_tags
+                .split(';')
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| match tag.find('=') {
+                    Some(pos) => (tag[..pos].to_string(), unescape_tag(&tag[pos + 1..])),
+                    None => (tag.to_string(), String::new()),
+                })
+                .collect();
+            rest = tail;
+        }
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (source, tail) = split_word(prefixed);
+            prefix = Some(source.to_string());
+            rest = tail;
+        }
+
+        let (command, mut rest) = split_word(rest);
+        if command.is_empty() {
+            return Err(format!("irc line without command: {}", line).into());
+        }
+        let mut params = vec![];
+        while !rest.is_empty() {
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_string());
+                break;
+            }
+            let (param, tail) = split_word(rest);
+            params.push(param.to_string());
+            rest = tail;
+        }
+
+        Ok(Message {
+            tags,
+            prefix,
+            command: command.to_string(),
+            params,
+        })
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Nick part of the prefix, `nick` of `nick!user@host`.
+    pub fn nick(&self) -> Option<&str> {
+        self.prefix
+            .as_deref()
+            .map(|prefix| prefix.split('!').next().unwrap_or(prefix))
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Channel login of a PRIVMSG or JOIN, without the `#`.
+    pub fn channel(&self) -> Option<&str> {
+        self.params
+            .first()
+            .and_then(|param| param.strip_prefix('#'))
+    }
+
+    /// Last parameter, the chat text of a PRIVMSG.
+    pub fn text(&self) -> Option<&str> {
+        self.params.last().map(String::as_str)
+    }
+
+    /// Emote names used in a PRIVMSG, taken from the `emotes` tag and the
+    /// text, once per occurrence.
+    pub fn emotes(&self) -> Vec<String> {
+        let (emotes, text) = match (self.tag("emotes"), self.text()) {
+            (Some(emotes), Some(text)) => (emotes, text),
+            _ => return vec![],
+        };
+        // emote positions count characters, not bytes
+        let chars: Vec<char> = text.chars().collect();
+        emotes
+            .split('/')
+            .filter_map(|emote| emote.split(':').nth(1))
+            .flat_map(|ranges| ranges.split(','))
+            .filter_map(|range| {
+                let mut bounds = range.split('-').map(|b| b.parse::<usize>());
+                match (bounds.next(), bounds.next()) {
+                    (Some(Ok(start)), Some(Ok(end))) if start <= end && end < chars.len() => {
+                        Some(chars[start..=end].iter().collect())
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+fn split_word(input: &str) -> (&str, &str) {
+    match input.find(' ') {
+        Some(pos) => (&input[..pos], input[pos + 1..].trim_start_matches(' ')),
+        None => (input, ""),
+    }
+}
+
+fn unescape_tag(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => out.push(';'),
+            Some('s') => out.push(' '),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Chat activity of one channel while it was recorded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelActivity {
+    channel: String,
+    messages: usize,
+    chatters: HashSet<String>,
+    emotes: HashMap<String, usize>,
+    recorded: Duration,
+}
+
+impl ChannelActivity {
+    fn new(channel: &str) -> Self {
+        ChannelActivity {
+            channel: channel.to_string(),
+            messages: 0,
+            chatters: HashSet::new(),
+            emotes: HashMap::new(),
+            recorded: Duration::from_secs(0),
+        }
+    }
+
+    fn add(&mut self, message: &Message) {
+        self.messages += 1;
+        if let Some(chatter) = message.nick() {
+            self.chatters.insert(chatter.to_string());
+        }
+        for emote in message.emotes() {
+            *self.emotes.entry(emote).or_insert(0) += 1;
+        }
+    }
+
+    pub fn channel(&self) -> &String {
+        &self.channel
+    }
+
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    pub fn unique_chatters(&self) -> usize {
+        self.chatters.len()
+    }
+
+    /// Emote name and number of uses, most used first.
+    pub fn top_emotes(&self) -> Vec<(&String, usize)> {
+        let mut emotes: Vec<_> = self.emotes.iter().map(|(e, c)| (e, *c)).collect();
+        emotes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        emotes
+    }
+
+    pub fn recorded(&self) -> Duration {
+        self.recorded
+    }
+
+    /// Messages per minute.
+    pub fn message_rate(&self) -> f64 {
+        let minutes = self.recorded.as_secs_f64() / 60.0;
+        if minutes > 0.0 {
+            self.messages as f64 / minutes
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Recording that ended early, with the activity counted until then.
+#[derive(Debug)]
+pub struct RecordError {
+    pub activity: Vec<ChannelActivity>,
+    pub source: AsyncError,
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl std::error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Why a connection ended.
+enum Disconnect {
+    Deadline,
+    /// `joined` if the server confirmed a JOIN before.
+    Reconnect {
+        reason: String,
+        joined: bool,
+    },
+}
+
+/// Joins the chat of `channels` (logins) and records their activity for
+/// `duration`. Dropped connections are reconnected and the channels
+/// joined again, messages in between are lost. Reconnects are counted
+/// from the last connection that joined.
+pub async fn record_activity(
+    config: &ChatConfig,
+    channels: Vec<&String>,
+    duration: Duration,
+) -> Result<Vec<ChannelActivity>, RecordError> {
+    let started = Instant::now();
+    let deadline = started + duration;
+    let channels: Vec<String> = channels.iter().map(|c| c.to_lowercase()).collect();
+    let mut activity: HashMap<String, ChannelActivity> = channels
+        .iter()
+        .map(|c| (c.clone(), ChannelActivity::new(c)))
+        .collect();
+
+    let mut reconnects = 0;
+    let error = loop {
+        match read_chat(config, &channels, &mut activity, deadline).await {
+            Ok(Disconnect::Deadline) => break None,
+            Ok(Disconnect::Reconnect { reason, joined }) => {
+                if joined {
+                    reconnects = 0;
+                }
+                if reconnects == config.max_reconnects {
+                    break Some(format!("chat connection lost too often, {}", reason).into());
+                }
+                reconnects += 1;
+                task::sleep(config.reconnect_delay).await;
+            }
+            Err(e) => break Some(e),
+        }
+    };
+
+    let recorded = started.elapsed();
+    let activity = channels
+        .iter()
+        .filter_map(|c| activity.remove(c))
+        .map(|mut a| {
+            a.recorded = recorded;
+            a
+        })
+        .collect();
+    match error {
+        None => Ok(activity),
+        Some(source) => Err(RecordError { activity, source }),
+    }
+}
+
+/// One connection: logs in, joins all channels and counts PRIVMSGs until
+/// the deadline or the connection is lost. Failed logins are errors.
+async fn read_chat(
+    config: &ChatConfig,
+    channels: &[String],
+    activity: &mut HashMap<String, ChannelActivity>,
+    deadline: Instant,
+) -> Result<Disconnect, AsyncError> {
+    let stream = match TcpStream::connect(&config.address).await {
+        Ok(stream) => stream,
+        Err(e) => return Ok(lost(e, false)),
+    };
+    let mut writer = &stream;
+    let mut reader = BufReader::new(&stream);
+
+    let mut login = vec!["CAP REQ :twitch.tv/tags twitch.tv/commands".to_string()];
+    match &config.login {
+        Login::Anonymous => login.push(format!("NICK {}", ANONYMOUS_NICK)),
+        Login::OAuth { nick, token } => {
+            let token = token.trim_start_matches("oauth:");
+            login.push(format!("PASS oauth:{}", token));
+            login.push(format!("NICK {}", nick.to_lowercase()));
+        }
+    }
+    for line in login {
+        if let Err(e) = writer.write_all(format!("{}\r\n", line).as_bytes()).await {
+            return Ok(lost(e, false));
+        }
+    }
+
+    let mut pending_joins = channels.chunks(JOIN_CHUNK_SIZE);
+    let mut next_join = Instant::now();
+    let mut joined = false;
+    // kept across wake-ups, a line may arrive in parts
+    let mut line = vec![];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(Disconnect::Deadline);
+        }
+        if now >= next_join {
+            if let Some(chunk) = pending_joins.next() {
+                let join = chunk
+                    .iter()
+                    .map(|c| format!("#{}", c))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                if let Err(e) = writer
+                    .write_all(format!("JOIN {}\r\n", join).as_bytes())
+                    .await
+                {
+                    return Ok(lost(e, joined));
+                }
+                next_join = now + config.join_interval;
+            }
+        }
+
+        let wake_up = if pending_joins.len() > 0 {
+            deadline.min(next_join)
+        } else {
+            deadline
+        };
+        match future::timeout(wake_up - now, reader.read_until(b'\n', &mut line)).await {
+            Err(_timeout) => continue,
+            Ok(Err(e)) => return Ok(lost(e, joined)),
+            Ok(Ok(0)) => return Ok(lost("connection closed", joined)),
+            Ok(Ok(_)) => {}
+        }
+        let message = Message::parse(&String::from_utf8_lossy(&line));
+        line.clear();
+        let message = match message {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+
+        match message.command() {
+            "PING" => {
+                let pong = format!("PONG :{}\r\n", message.text().unwrap_or("tmi.twitch.tv"));
+                if let Err(e) = writer.write_all(pong.as_bytes()).await {
+                    return Ok(lost(e, joined));
+                }
+            }
+            "RECONNECT" => return Ok(lost("server asked to reconnect", joined)),
+            "JOIN" => joined = true,
+            "NOTICE"
+                if message
+                    .text()
+                    .unwrap_or("")
+                    .contains("authentication failed") =>
+            {
+                return Err(format!("chat login failed: {}", message.text().unwrap()).into())
+            }
+            "PRIVMSG" => {
+                if let Some(channel) = message.channel().and_then(|c| activity.get_mut(c)) {
+                    channel.add(&message);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lost(reason: impl ToString, joined: bool) -> Disconnect {
+    Disconnect::Reconnect {
+        reason: reason.to_string(),
+        joined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_privmsg_with_tags() {
+        let message = Message::parse(
+            "@badge-info=;display-name=Ronni;emotes=25:0-4,12-16/1902:6-10;\
+             system-msg=hello\\sworld\\:) :ronni!ronni@ronni.tmi.twitch.tv \
+             PRIVMSG #dallas :Kappa Keepo Kappa\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(message.command(), "PRIVMSG");
+        assert_eq!(message.nick(), Some("ronni"));
+        assert_eq!(message.channel(), Some("dallas"));
+        assert_eq!(message.text(), Some("Kappa Keepo Kappa"));
+        assert_eq!(message.tag("display-name"), Some("Ronni"));
+        assert_eq!(message.tag("badge-info"), Some(""));
+        assert_eq!(message.tag("system-msg"), Some("hello world;)"));
+        assert_eq!(message.emotes(), vec!["Kappa", "Kappa", "Keepo"]);
+    }
+
+    #[test]
+    fn test_parse_without_tags_and_prefix() {
+        let message = Message::parse("PING :tmi.twitch.tv").unwrap();
+
+        assert_eq!(message.command(), "PING");
+        assert_eq!(message.prefix(), None);
+        assert_eq!(message.params(), ["tmi.twitch.tv"]);
+
+        let message = Message::parse(":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!").unwrap();
+        assert_eq!(message.command(), "001");
+        assert_eq!(message.params(), ["justinfan12345", "Welcome, GLHF!"]);
+
+        assert!(Message::parse(":tmi.twitch.tv").is_err());
+    }
+
+    #[test]
+    fn test_emote_positions_count_chars() {
+        let message = Message::parse("@emotes=25:4-8 :a!a@a PRIVMSG #b :äöü Kappa").unwrap();
+
+        assert_eq!(message.emotes(), vec!["Kappa"]);
+    }
+}
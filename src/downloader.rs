@@ -1,254 +1,2177 @@
-use crate::AsyncError;
-use async_std::{
-    prelude::*,
-    sync::{Arc, Mutex},
-    task,
-};
-use futures::{channel::mpsc, future::join_all, sink::SinkExt};
-use serde::Deserialize;
+use crate::cassette::{scrub_url, Cassette, Interaction, Player, Recorder};
+use crate::error::Error;
+use crate::metrics::{self, Metrics};
+use crate::nom_parser::Streamer;
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport, SurfTransport};
+use async_std::{future, prelude::*, sync::Arc, task};
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone, Utc};
+use futures::future::{join_all, BoxFuture};
+use futures::{stream, stream::FuturesUnordered};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::hash_map::{Entry, HashMap};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info_span, warn, Instrument};
 
-type Sender<T> = mpsc::UnboundedSender<T>;
-type Receiver<T> = mpsc::UnboundedReceiver<T>;
+type DownloadResult = Result<String, Error>;
+pub async fn download_file(config: &Config, url: &str) -> DownloadResult {
+    let res = send(config, "GET", url, false, vec![], None).await?;
+    Ok(res.body)
+}
+
+const CLIENT_ID_KEY: &str = "TWITCH_CLIENT_ID";
+const BEARER_TOKEN_KEY: &str = "BEARER_TOKEN_KEY";
+const LOGIN_CHUNK_SIZE: usize = 100;
+/// Items per page of the paginated endpoints, the Helix maximum.
+const PAGE_SIZE: usize = 100;
+const HELIX_URL: &str = "https://api.twitch.tv/helix";
+const AUTH_URL: &str = "https://id.twitch.tv/oauth2";
+const MAX_RETRIES: u32 = 3;
+/// Requests in flight of [`Strategy::Concurrent`] by default.
+const DEFAULT_WORKERS: usize = 4;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Game ids of the Twitch categories developer streams belong in,
+/// Science & Technology and Software and Game Development.
+const DEVELOPMENT_GAME_IDS: [&str; 2] = ["509670", "1469308723"];
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+/// Response headers kept in recorded interactions.
+const RECORDED_HEADERS: [&str; 6] = [
+    "Content-Type",
+    "ETag",
+    "Last-Modified",
+    "Ratelimit-Limit",
+    "Ratelimit-Remaining",
+    "Ratelimit-Reset",
+];
+
+/// Endpoints and credentials used for all Twitch requests.
+#[derive(Clone, Debug)]
+pub struct Config {
+    helix_url: String,
+    auth_url: String,
+    client_id: String,
+    bearer_token: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+    request_timeout: Duration,
+    crawl_deadline: Option<Duration>,
+    duplicate_policy: DuplicatePolicy,
+    traffic: Traffic,
+    transport: Arc<dyn HttpTransport>,
+    metrics: Metrics,
+}
+
+/// What to keep when several README entries or responses resolve to the
+/// same Twitch user.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuplicatePolicy {
+    #[default]
+    KeepFirst,
+    KeepLast,
+    /// Keeps the first user, tagged with the names of all README entries.
+    MergeNames,
+}
+
+/// Problem found during a crawl that did not stop it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// Several README entries point to the same login.
+    DuplicateEntry { login: String, names: Vec<String> },
+    /// Twitch returned the same user id more than once.
+    DuplicateUser { id: String },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::DuplicateEntry { login, names } => {
+                write!(
+                    f,
+                    "{} is listed {} times: {}",
+                    login,
+                    names.len(),
+                    names.join(", ")
+                )
+            }
+            Diagnostic::DuplicateUser { id } => write!(f, "user {} returned more than once", id),
+        }
+    }
+}
+
+/// Where responses come from, the network or a cassette.
+#[derive(Clone, Debug)]
+enum Traffic {
+    Live,
+    Record(Recorder),
+    Replay(Player),
+}
+
+impl Config {
+    pub fn new(client_id: impl Into<String>, bearer_token: impl Into<String>) -> Self {
+        Config {
+            helix_url: HELIX_URL.to_string(),
+            auth_url: AUTH_URL.to_string(),
+            client_id: client_id.into(),
+            bearer_token: bearer_token.into(),
+            max_retries: MAX_RETRIES,
+            retry_backoff: Duration::from_millis(500),
+            request_timeout: REQUEST_TIMEOUT,
+            crawl_deadline: None,
+            duplicate_policy: DuplicatePolicy::default(),
+            traffic: Traffic::Live,
+            transport: Arc::new(SurfTransport::new()),
+            metrics: Metrics::new(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        let client_id = std::env::var(CLIENT_ID_KEY).map_err(|e| Error::Config {
+            message: format!("twitch client id not found, key {}", CLIENT_ID_KEY),
+            source: Some(e.into()),
+        })?;
+        let bearer_token = std::env::var(BEARER_TOKEN_KEY).map_err(|e| Error::Config {
+            message: format!("twitch bearer token not found, key {}", BEARER_TOKEN_KEY),
+            source: Some(e.into()),
+        })?;
+        Ok(Config::new(client_id, bearer_token))
+    }
+
+    pub fn with_helix_url(mut self, helix_url: impl Into<String>) -> Self {
+        self.helix_url = helix_url.into();
+        self
+    }
+
+    pub fn with_auth_url(mut self, auth_url: impl Into<String>) -> Self {
+        self.auth_url = auth_url.into();
+        self
+    }
+
+    /// Retries after a 429 or 5xx response, 0 disables retrying.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Base delay before retrying a 5xx response, doubled on every attempt.
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Time a single request may take until the whole response is read.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Time a [`fetch_users`] crawl may take. Logins not done by then
+    /// are returned as unfinished, next to the partial result.
+    pub fn with_crawl_deadline(mut self, crawl_deadline: Duration) -> Self {
+        self.crawl_deadline = Some(crawl_deadline);
+        self
+    }
+
+    /// How duplicate README entries and users are resolved.
+    pub fn with_duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    /// Sends all requests through `transport` instead of surf.
+    pub fn with_transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Counts requests and results into `metrics` instead of a fresh
+    /// instance, e.g. to share them between configs.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Records every request and response into `recorder`.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.traffic = Traffic::Record(recorder);
+        self
+    }
+
+    /// Serves all requests from `cassette` instead of the network.
+    pub fn with_replay(mut self, cassette: Cassette) -> Self {
+        self.traffic = Traffic::Replay(Player::new(cassette));
+        self
+    }
+
+    /// Replaces the bearer token with an app access token from the
+    /// client credentials flow.
+    pub async fn with_app_access_token(mut self, client_secret: &str) -> Result<Self, Error> {
+        let url = format!(
+            "{}/token?client_id={}&client_secret={}&grant_type=client_credentials",
+            self.auth_url, self.client_id, client_secret
+        );
+        let res = send(&self, "POST", &url, false, vec![], None).await?;
+        match res.status {
+            200 => {}
+            // a wrong client id or secret is a 400
+            400..=403 => {
+                return Err(Error::Auth {
+                    status: res.status,
+                    url,
+                    message: error_message(&res.body),
+                })
+            }
+            _ => return Err(status_error(&url, &res)),
+        }
+        let AppAccessToken { access_token } =
+            serde_json::from_str(&res.body).map_err(|e| Error::decode(&url, &res.body, e))?;
+        self.bearer_token = access_token;
+        Ok(self)
+    }
+
+    pub fn helix_url(&self) -> &str {
+        &self.helix_url
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
+#[derive(Deserialize)]
+struct AppAccessToken {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct UsersData {
+    #[serde(rename = "data")]
+    users: Vec<TwitchUserData>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TwitchUserData {
+    id: String,
+    login: String,
+    display_name: String,
+    #[serde(rename = "type")]
+    user_type: UserType,
+    broadcaster_type: BroadcasterType,
+    description: String,
+    profile_image_url: String,
+    offline_image_url: String,
+    view_count: u32,
+    created_at: DateTime<Utc>,
+    /// Only sent for tokens with the `user:read:email` scope.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    // not sent by Helix, filled in by the enrichments
+    #[serde(default)]
+    follower_count: u32,
+    #[serde(default)]
+    followers: Option<Vec<Follower>>,
+    #[serde(default)]
+    streamer_name: String,
+    #[serde(default)]
+    live_stream: Option<LiveStream>,
+    #[serde(default)]
+    channel: Option<ChannelInformation>,
+}
+
+impl TwitchUserData {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn login(&self) -> &String {
+        &self.login
+    }
+
+    /// README name of the streamer this user was looked up for.
+    pub fn streamer_name(&self) -> &String {
+        &self.streamer_name
+    }
+
+    pub fn display_name(&self) -> &String {
+        &self.display_name
+    }
+
+    pub fn view_count(&self) -> u32 {
+        self.view_count
+    }
+
+    pub fn user_type(&self) -> UserType {
+        self.user_type
+    }
+
+    pub fn broadcaster_type(&self) -> BroadcasterType {
+        self.broadcaster_type
+    }
+
+    pub fn description(&self) -> &String {
+        &self.description
+    }
+
+    pub fn profile_image_url(&self) -> &String {
+        &self.profile_image_url
+    }
+
+    /// Empty if the user has no offline image.
+    pub fn offline_image_url(&self) -> &String {
+        &self.offline_image_url
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// Time since the account was created.
+    pub fn account_age(&self, now: DateTime<Utc>) -> chrono::Duration {
+        now - self.created_at
+    }
+
+    pub fn email(&self) -> Option<&String> {
+        self.email.as_ref()
+    }
+
+    /// 0 unless fetched with the followers enrichment.
+    pub fn follower_count(&self) -> u32 {
+        self.follower_count
+    }
+
+    /// Current stream if live, needs the streams enrichment.
+    pub fn live_stream(&self) -> Option<&LiveStream> {
+        self.live_stream.as_ref()
+    }
+
+    /// Needs the channels enrichment.
+    pub fn channel(&self) -> Option<&ChannelInformation> {
+        self.channel.as_ref()
+    }
+
+    /// Every follower, newest follow first, needs the follower lists
+    /// enrichment.
+    pub fn followers(&self) -> Option<&[Follower]> {
+        self.followers.as_deref()
+    }
+
+    fn set_followers(&mut self, data: FollowerData) {
+        match data {
+            FollowerData::Total(total) => self.follower_count = total,
+            FollowerData::List { total, followers } => {
+                self.follower_count = total;
+                self.followers = Some(followers);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserType {
+    Staff,
+    Admin,
+    GlobalMod,
+    #[serde(rename = "")]
+    Normal,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BroadcasterType {
+    Partner,
+    Affiliate,
+    #[serde(rename = "")]
+    Normal,
+    #[serde(other)]
+    Other,
+}
+
+/// Users found for the requested streamers, and the streamers Twitch
+/// returned no user for.
+#[derive(Debug)]
+pub struct UserLookup {
+    /// In README order.
+    pub users: Vec<TwitchUserData>,
+    pub missing: Vec<MissingLogin>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Logins whose lookup timed out or was cut off by the crawl deadline,
+    /// their user is absent or lacks an enrichment.
+    pub unfinished: Vec<String>,
+}
+
+impl UserLookup {
+    /// User with the Twitch id `id`.
+    pub fn user(&self, id: &str) -> Option<&TwitchUserData> {
+        self.users.iter().find(|u| u.id == id)
+    }
+}
+
+/// README entry whose login is unknown to Twitch, renamed, banned or deleted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingLogin {
+    login: String,
+    name: String,
+}
+
+impl MissingLogin {
+    pub fn login(&self) -> &String {
+        &self.login
+    }
+
+    /// Name of the README entry.
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TwitchFollowers {
+    total: u32,
+}
+
+/// Entry of a follower list.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Follower {
+    from_id: String,
+    #[serde(default)]
+    from_login: String,
+    followed_at: DateTime<Utc>,
+}
+
+impl Follower {
+    /// User id of the follower.
+    pub fn id(&self) -> &String {
+        &self.from_id
+    }
+
+    pub fn login(&self) -> &String {
+        &self.from_login
+    }
+
+    pub fn followed_at(&self) -> DateTime<Utc> {
+        self.followed_at
+    }
+}
+
+/// New follows per UTC day, days without follows left out.
+pub fn follows_per_day(followers: &[Follower]) -> BTreeMap<NaiveDate, usize> {
+    let mut days = BTreeMap::new();
+    for follower in followers {
+        *days.entry(follower.followed_at.date_naive()).or_insert(0) += 1;
+    }
+    days
+}
+
+/// Followers following at least `min` of `users`, by follower id with the
+/// ids of the users they follow. Needs the follower lists enrichment.
+pub fn shared_followers(users: &[TwitchUserData], min: usize) -> BTreeMap<String, Vec<String>> {
+    let mut followed: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for user in users {
+        for follower in user.followers().unwrap_or_default() {
+            followed
+                .entry(follower.from_id.clone())
+                .or_default()
+                .push(user.id.clone());
+        }
+    }
+    followed.retain(|_, users| users.len() >= min);
+    followed
+}
+
+/// Followers of a user as a follower lookup returns them. `total` is the
+/// count Helix reports, the list may differ from it by follows made while
+/// paging.
+enum FollowerData {
+    Total(u32),
+    List {
+        total: u32,
+        followers: Vec<Follower>,
+    },
+}
+
+/// One page of a paginated Helix response.
+#[derive(Deserialize)]
+struct Page<T> {
+    data: Vec<T>,
+    /// Sent by the endpoints that count their items, e.g. follows.
+    #[serde(default)]
+    total: Option<u32>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+/// Items of the pages read, and the `total` of the first page.
+struct Listing<T> {
+    items: Vec<T>,
+    total: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+struct Pagination {
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoType {
+    Archive,
+    Highlight,
+    Upload,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Video {
+    id: String,
+    user_id: String,
+    title: String,
+    published_at: DateTime<Utc>,
+    view_count: u32,
+    duration: String,
+    #[serde(rename = "type")]
+    video_type: VideoType,
+    url: String,
+}
+
+impl Video {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn user_id(&self) -> &String {
+        &self.user_id
+    }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    pub fn published_at(&self) -> DateTime<Utc> {
+        self.published_at
+    }
+
+    pub fn view_count(&self) -> u32 {
+        self.view_count
+    }
+
+    /// Length of the video, Helix sends it as e.g. `3h8m33s`.
+    pub fn duration(&self) -> Duration {
+        parse_video_duration(&self.duration)
+    }
+
+    pub fn video_type(&self) -> VideoType {
+        self.video_type
+    }
+
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+}
+
+/// How much a streamer broadcast since a point in time, from their videos.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BroadcastActivity {
+    pub broadcasts: usize,
+    pub total_duration: Duration,
+    pub last_broadcast: Option<DateTime<Utc>>,
+}
+
+impl BroadcastActivity {
+    /// Counts archived broadcasts published after `since`, highlights are
+    /// cut from broadcasts and would count them twice.
+    pub fn from_videos(videos: &[Video], since: DateTime<Utc>) -> Self {
+        let archives = videos.iter().filter(|v| v.video_type == VideoType::Archive);
+        let last_broadcast = archives.clone().map(|v| v.published_at).max();
+        let recent: Vec<&Video> = archives.filter(|v| v.published_at >= since).collect();
+        BroadcastActivity {
+            broadcasts: recent.len(),
+            total_duration: recent.iter().map(|v| v.duration()).sum(),
+            last_broadcast,
+        }
+    }
+
+    /// No archived broadcast since `cutoff`.
+    pub fn inactive_since(&self, cutoff: DateTime<Utc>) -> bool {
+        self.last_broadcast.is_none_or(|last| last < cutoff)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Clip {
+    id: String,
+    url: String,
+    broadcaster_id: String,
+    broadcaster_name: String,
+    creator_id: String,
+    creator_name: String,
+    title: String,
+    view_count: u32,
+    created_at: DateTime<Utc>,
+}
+
+impl Clip {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+
+    pub fn broadcaster_id(&self) -> &String {
+        &self.broadcaster_id
+    }
+
+    pub fn broadcaster_name(&self) -> &String {
+        &self.broadcaster_name
+    }
+
+    pub fn creator_id(&self) -> &String {
+        &self.creator_id
+    }
+
+    pub fn creator_name(&self) -> &String {
+        &self.creator_name
+    }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    pub fn view_count(&self) -> u32 {
+        self.view_count
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChannelInformation {
+    broadcaster_id: String,
+    broadcaster_name: String,
+    broadcaster_language: String,
+    game_id: String,
+    game_name: String,
+    title: String,
+    /// Sent by `/helix/channels`, else looked up from the stream tags.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl ChannelInformation {
+    pub fn broadcaster_id(&self) -> &String {
+        &self.broadcaster_id
+    }
+
+    pub fn broadcaster_name(&self) -> &String {
+        &self.broadcaster_name
+    }
+
+    pub fn broadcaster_language(&self) -> &String {
+        &self.broadcaster_language
+    }
+
+    pub fn game_id(&self) -> &String {
+        &self.game_id
+    }
+
+    pub fn game_name(&self) -> &String {
+        &self.game_name
+    }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    /// Tags of the channel, or the English names of its stream tags.
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    /// Category is one of the categories developer streams belong in.
+    pub fn is_development_category(&self) -> bool {
+        DEVELOPMENT_GAME_IDS.contains(&self.game_id.as_str())
+    }
+
+    /// Topics, e.g. from the README, that are mentioned in the category,
+    /// title or tags of the channel, compared case-insensitively.
+    pub fn matching_topics<'a>(&self, topics: &[&'a str]) -> Vec<&'a str> {
+        let texts: Vec<String> = [&self.game_name, &self.title]
+            .iter()
+            .copied()
+            .chain(self.tags.iter())
+            .map(|text| text.to_lowercase())
+            .collect();
+        topics
+            .iter()
+            .filter(|topic| {
+                let topic = topic.to_lowercase();
+                texts.iter().any(|text| text.contains(&topic))
+            })
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct StreamTag {
+    localization_names: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ScheduleData {
+    data: Schedule,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Schedule {
+    broadcaster_id: String,
+    broadcaster_name: String,
+    broadcaster_login: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    segments: Vec<ScheduleSegment>,
+    vacation: Option<Vacation>,
+}
+
+impl Schedule {
+    pub fn broadcaster_id(&self) -> &String {
+        &self.broadcaster_id
+    }
+
+    pub fn broadcaster_name(&self) -> &String {
+        &self.broadcaster_name
+    }
+
+    pub fn broadcaster_login(&self) -> &String {
+        &self.broadcaster_login
+    }
+
+    pub fn segments(&self) -> &Vec<ScheduleSegment> {
+        &self.segments
+    }
+
+    pub fn vacation(&self) -> Option<&Vacation> {
+        self.vacation.as_ref()
+    }
+
+    /// Segments that are neither canceled nor during the vacation.
+    pub fn upcoming_segments(&self) -> impl Iterator<Item = &ScheduleSegment> {
+        self.segments.iter().filter(move |segment| {
+            segment.canceled_until.is_none()
+                && !self.vacation.as_ref().is_some_and(|vacation| {
+                    segment.start_time >= vacation.start_time
+                        && segment.start_time < vacation.end_time
+                })
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ScheduleSegment {
+    id: String,
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+    title: String,
+    canceled_until: Option<DateTime<Utc>>,
+    category: Option<Category>,
+    is_recurring: bool,
+}
+
+impl ScheduleSegment {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn start_time(&self) -> DateTime<Utc> {
+        self.start_time
+    }
+
+    pub fn end_time(&self) -> Option<DateTime<Utc>> {
+        self.end_time
+    }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    pub fn canceled_until(&self) -> Option<DateTime<Utc>> {
+        self.canceled_until
+    }
+
+    pub fn category(&self) -> Option<&Category> {
+        self.category.as_ref()
+    }
+
+    pub fn is_recurring(&self) -> bool {
+        self.is_recurring
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Vacation {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+fn null_as_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LiveStream {
+    user_id: String,
+    user_login: String,
+    user_name: String,
+    game_id: String,
+    game_name: String,
+    title: String,
+    viewer_count: u32,
+    started_at: DateTime<Utc>,
+    language: String,
+    thumbnail_url: String,
+}
+
+impl LiveStream {
+    pub fn user_id(&self) -> &String {
+        &self.user_id
+    }
+
+    pub fn user_login(&self) -> &String {
+        &self.user_login
+    }
+
+    pub fn user_name(&self) -> &String {
+        &self.user_name
+    }
+
+    pub fn game_id(&self) -> &String {
+        &self.game_id
+    }
+
+    pub fn game_name(&self) -> &String {
+        &self.game_name
+    }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    pub fn viewer_count(&self) -> u32 {
+        self.viewer_count
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn language(&self) -> &String {
+        &self.language
+    }
+
+    /// Thumbnail url with the `{width}` and `{height}` placeholders filled in.
+    pub fn thumbnail_url(&self, width: u32, height: u32) -> String {
+        self.thumbnail_url
+            .replace("{width}", &width.to_string())
+            .replace("{height}", &height.to_string())
+    }
+}
 
-type DownloadResult = Result<String, AsyncError>;
-pub async fn download_file(url: &str) -> DownloadResult {
-    let mut res = surf::get(url).await?;
-    Ok(res.body_string().await?)
+/// How [`fetch_users`] runs its requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// One request at a time.
+    Sequential,
+    /// Up to `workers` requests at a time.
+    Concurrent { workers: usize },
 }
 
-const CLIENT_ID_KEY: &str = "TWITCH_CLIENT_ID";
-const BEARER_TOKEN_KEY: &str = "BEARER_TOKEN_KEY";
-const LOGIN_CHUNK_SIZE: usize = 100;
+impl Default for Strategy {
+    fn default() -> Self {
+        Strategy::Concurrent {
+            workers: DEFAULT_WORKERS,
+        }
+    }
+}
 
-#[derive(Deserialize)]
-struct UsersData {
-    #[serde(rename = "data")]
-    users: Vec<TwitchUserData>,
+impl Strategy {
+    fn workers(self) -> usize {
+        match self {
+            Strategy::Sequential => 1,
+            Strategy::Concurrent { workers } => workers.max(1),
+        }
+    }
 }
 
-#[derive(Deserialize, Clone, Debug)]
-pub struct TwitchUserData {
-    id: String,
-    display_name: String,
-    view_count: u32,
-    description: String,
-    #[serde(skip)]
-    follower_count: u32,
+/// What [`fetch_users`] looks up on top of the users themselves.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Enrichments {
+    /// Follower count of every user.
+    pub followers: bool,
+    /// Every follower of every user with the time they followed, a request
+    /// per 100 followers. Sets the follower counts too.
+    pub follower_lists: bool,
+    /// Live stream of the users that are live right now.
+    pub streams: bool,
+    /// Channel information with stream tags.
+    pub channels: bool,
 }
 
-impl TwitchUserData {
-    fn set_follower_count(&mut self, count: u32) {
-        self.follower_count = count;
+impl Enrichments {
+    pub fn none() -> Self {
+        Enrichments::default()
+    }
+
+    pub fn all() -> Self {
+        Enrichments {
+            followers: true,
+            follower_lists: true,
+            streams: true,
+            channels: true,
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct TwitchFollowers {
-    total: u32,
-    #[serde(skip)]
-    id: String,
+/// Options of [`fetch_users`], concurrent with four workers and without
+/// enrichments by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FetchOptions {
+    strategy: Strategy,
+    enrichments: Enrichments,
+}
+
+impl FetchOptions {
+    pub fn new() -> Self {
+        FetchOptions::default()
+    }
+
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_enrichments(mut self, enrichments: Enrichments) -> Self {
+        self.enrichments = enrichments;
+        self
+    }
+
+    pub fn with_followers(mut self) -> Self {
+        self.enrichments.followers = true;
+        self
+    }
+
+    pub fn with_follower_lists(mut self) -> Self {
+        self.enrichments.follower_lists = true;
+        self
+    }
+
+    pub fn with_streams(mut self) -> Self {
+        self.enrichments.streams = true;
+        self
+    }
+
+    pub fn with_channels(mut self) -> Self {
+        self.enrichments.channels = true;
+        self
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn enrichments(&self) -> Enrichments {
+        self.enrichments
+    }
+}
+
+/// Twitch users of the streamers in README order, in batches of 100
+/// logins, plus the enrichments `options` asks for. The enrichments of a
+/// batch are looked up as soon as its users are back, sharing the workers
+/// of the user batches.
+#[tracing::instrument(name = "users", skip_all, fields(streamers = streamers.len()))]
+pub async fn fetch_users(
+    config: &Config,
+    streamers: Vec<&Streamer>,
+    options: &FetchOptions,
+) -> Result<UserLookup, Error> {
+    let login_names = unique_logins(&streamers);
+    let mut pipeline = Pipeline::new(config, &login_names, options);
+    let mut batches: Vec<Option<FinishedBatch>> = pipeline.batches.iter().map(|_| None).collect();
+    while let Some(batch) = pipeline.next_batch().await? {
+        let index = batch.index;
+        batches[index] = Some(batch);
+    }
+
+    let mut users: Vec<TwitchUserData> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    // logins of dropped duplicates, by the position of the user kept
+    let mut aliases: HashMap<String, usize> = HashMap::new();
+    let mut diagnostics = vec![];
+    let mut unfinished_batches = vec![];
+    let mut unfinished = vec![];
+    for batch in batches.into_iter().flatten() {
+        let data = match batch.users {
+            Some(data) => data,
+            None => {
+                unfinished_batches.extend(batch.unfinished);
+                continue;
+            }
+        };
+        unfinished.extend(batch.unfinished);
+        for user in data {
+            match positions.entry(user.id.clone()) {
+                Entry::Occupied(pos) => {
+                    diagnostics.push(Diagnostic::DuplicateUser {
+                        id: user.id.clone(),
+                    });
+                    let pos = *pos.get();
+                    let dropped = if config.duplicate_policy == DuplicatePolicy::KeepLast {
+                        std::mem::replace(&mut users[pos], user)
+                    } else {
+                        user
+                    };
+                    aliases.insert(dropped.login.to_lowercase(), pos);
+                }
+                Entry::Vacant(pos) => {
+                    pos.insert(users.len());
+                    users.push(user);
+                }
+            }
+        }
+    }
+    let (missing, entry_diagnostics) =
+        link_streamers(&mut users, aliases, &streamers, config.duplicate_policy);
+    diagnostics.extend(entry_diagnostics);
+    let missing = record_logins(config, login_names.len(), missing, &unfinished_batches);
+    unfinished_batches.extend(unfinished);
+
+    Ok(UserLookup {
+        users,
+        missing,
+        diagnostics,
+        unfinished: unfinished_batches,
+    })
+}
+
+/// Like [`fetch_users`], but yields the users of every Helix batch as soon
+/// as it and its enrichments are done, in the order the batches finish.
+/// A failed batch is yielded as error and the crawl goes on. Logins cut off
+/// by the crawl deadline are logged, their users are yielded without the
+/// unfinished enrichments. Users Twitch returns twice are yielded once,
+/// logins without user are not reported.
+pub fn stream_users<'a>(
+    config: &'a Config,
+    streamers: Vec<&'a Streamer>,
+    options: &FetchOptions,
+) -> impl Stream<Item = Result<TwitchUserData, Error>> + 'a {
+    let login_names = unique_logins(&streamers);
+    let names: HashMap<String, String> = group_entries(&streamers)
+        .into_iter()
+        .map(|entry| {
+            let names: Vec<String> = entry.iter().map(|s| s.name().clone()).collect();
+            (
+                entry[0].login_name().to_lowercase(),
+                entry_name(&names, config.duplicate_policy),
+            )
+        })
+        .collect();
+    let state = StreamState {
+        pipeline: Pipeline::new(config, &login_names, options),
+        pending: VecDeque::new(),
+        seen: HashSet::new(),
+        names,
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(user) = state.pending.pop_front() {
+                return Some((Ok(user), state));
+            }
+            let batch = match state.pipeline.next_batch().await {
+                Ok(Some(batch)) => batch,
+                Ok(None) => return None,
+                Err(e) => return Some((Err(e), state)),
+            };
+            if !batch.unfinished.is_empty() {
+                warn!(
+                    logins = %batch.unfinished.join(", "),
+                    "lookups unfinished at the crawl deadline"
+                );
+            }
+            for mut user in batch.users.into_iter().flatten() {
+                if state.seen.insert(user.id.clone()) {
+                    if let Some(name) = state.names.get(&user.login.to_lowercase()) {
+                        user.streamer_name = name.clone();
+                    }
+                    state.pending.push_back(user);
+                }
+            }
+        }
+    })
+}
+
+struct StreamState<'a> {
+    pipeline: Pipeline<'a>,
+    pending: VecDeque<TwitchUserData>,
+    seen: HashSet<String>,
+    /// README name by lowercase login.
+    names: HashMap<String, String>,
+}
+
+/// Request of the users pipeline.
+enum Job {
+    Users {
+        batch: usize,
+        url: String,
+        batch_size: usize,
+    },
+    Followers {
+        batch: usize,
+        id: String,
+        lists: bool,
+    },
+    Streams {
+        batch: usize,
+        logins: Vec<String>,
+    },
+    Channels {
+        batch: usize,
+        ids: Vec<String>,
+    },
+    /// Fallback for a channel without tags.
+    StreamTags {
+        batch: usize,
+        id: String,
+    },
+}
+
+/// Finished request of the users pipeline, `None` past the crawl deadline.
+enum Done {
+    Users {
+        batch: usize,
+        res: Option<Result<Vec<TwitchUserData>, Error>>,
+    },
+    Followers {
+        batch: usize,
+        id: String,
+        res: Option<Result<FollowerData, Error>>,
+    },
+    Streams {
+        batch: usize,
+        res: Option<Result<Vec<LiveStream>, Error>>,
+    },
+    Channels {
+        batch: usize,
+        res: Option<Result<Vec<ChannelInformation>, Error>>,
+    },
+    StreamTags {
+        batch: usize,
+        id: String,
+        res: Option<Result<Vec<String>, Error>>,
+    },
+}
+
+impl Done {
+    fn batch(&self) -> usize {
+        match self {
+            Done::Users { batch, .. }
+            | Done::Followers { batch, .. }
+            | Done::Streams { batch, .. }
+            | Done::Channels { batch, .. }
+            | Done::StreamTags { batch, .. } => *batch,
+        }
+    }
+}
+
+/// A user batch of the pipeline and its enrichments.
+struct BatchState {
+    logins: Vec<String>,
+    users: Option<Vec<TwitchUserData>>,
+    /// Enrichment requests still running.
+    pending: usize,
+    /// Logins with an unfinished enrichment.
+    unfinished: HashSet<String>,
+    finished: bool,
+}
+
+/// User batch with all its enrichments done.
+struct FinishedBatch {
+    index: usize,
+    /// `None` if the users weren't looked up before the crawl deadline.
+    users: Option<Vec<TwitchUserData>>,
+    /// Logins whose user or one of its enrichments is unfinished.
+    unfinished: Vec<String>,
+}
+
+/// Looks up user batches of 100 logins and the enrichments of their users,
+/// all requests sharing `workers`. The enrichments of a batch are queued as
+/// soon as its users are back and go before the remaining batches.
+struct Pipeline<'a> {
+    config: &'a Config,
+    enrichments: Enrichments,
+    workers: usize,
+    deadline: Option<Instant>,
+    batches: Vec<BatchState>,
+    batch_jobs: VecDeque<Job>,
+    enrichment_jobs: VecDeque<Job>,
+    running: FuturesUnordered<BoxFuture<'a, Done>>,
+    finished: VecDeque<FinishedBatch>,
+}
+
+impl<'a> Pipeline<'a> {
+    fn new(config: &'a Config, login_names: &[&String], options: &FetchOptions) -> Self {
+        let urls = build_users_urls(&config.helix_url, login_names, LOGIN_CHUNK_SIZE);
+        let chunks = login_names.chunks(LOGIN_CHUNK_SIZE);
+        let batch_jobs = urls
+            .into_iter()
+            .zip(chunks.clone())
+            .enumerate()
+            .map(|(batch, (url, logins))| Job::Users {
+                batch,
+                url,
+                batch_size: logins.len(),
+            })
+            .collect();
+        let batches = chunks
+            .map(|logins| BatchState {
+                logins: logins.iter().map(|l| l.to_string()).collect(),
+                users: None,
+                pending: 0,
+                unfinished: HashSet::new(),
+                finished: false,
+            })
+            .collect();
+        Pipeline {
+            config,
+            enrichments: options.enrichments,
+            workers: options.strategy.workers(),
+            deadline: config.crawl_deadline.map(|d| Instant::now() + d),
+            batches,
+            batch_jobs,
+            enrichment_jobs: VecDeque::new(),
+            running: FuturesUnordered::new(),
+            finished: VecDeque::new(),
+        }
+    }
+
+    /// Next batch with all its enrichments done, in the order they finish.
+    /// A failed request fails its batch, the other batches go on.
+    async fn next_batch(&mut self) -> Result<Option<FinishedBatch>, Error> {
+        loop {
+            if let Some(batch) = self.finished.pop_front() {
+                return Ok(Some(batch));
+            }
+            while self.running.len() < self.workers {
+                let job = self
+                    .enrichment_jobs
+                    .pop_front()
+                    .or_else(|| self.batch_jobs.pop_front());
+                match job {
+                    Some(job) => self.running.push(run_job(self.config, job, self.deadline)),
+                    None => break,
+                }
+            }
+            match self.running.next().await {
+                Some(done) => self.complete(done)?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn complete(&mut self, done: Done) -> Result<(), Error> {
+        let index = done.batch();
+        // the rest of a failed batch
+        if self.batches[index].finished {
+            return Ok(());
+        }
+        let batch = &mut self.batches[index];
+        match done {
+            Done::Users { res, .. } => match res {
+                Some(Ok(users)) => {
+                    let jobs = self.enrichment_jobs_for(index, &users);
+                    let batch = &mut self.batches[index];
+                    batch.pending = jobs.len();
+                    batch.users = Some(users);
+                    self.enrichment_jobs.extend(jobs);
+                }
+                Some(Err(e)) if !e.is_timeout() => return self.fail(index, e),
+                _ => {
+                    batch.unfinished.extend(batch.logins.iter().cloned());
+                }
+            },
+            Done::Followers { id, res, .. } => {
+                batch.pending -= 1;
+                let user = batch.users.iter_mut().flatten().find(|u| u.id == id);
+                match (res, user) {
+                    (Some(Ok(data)), Some(user)) => user.set_followers(data),
+                    (Some(Err(e)), _) if !e.is_timeout() => return self.fail(index, e),
+                    (_, Some(user)) => {
+                        batch.unfinished.insert(user.login.clone());
+                    }
+                    (_, None) => {}
+                }
+            }
+            Done::Streams { res, .. } => {
+                batch.pending -= 1;
+                match res {
+                    Some(Ok(streams)) => {
+                        let mut streams: HashMap<String, LiveStream> = streams
+                            .into_iter()
+                            .map(|s| (s.user_id.clone(), s))
+                            .collect();
+                        for user in batch.users.iter_mut().flatten() {
+                            user.live_stream = streams.remove(&user.id);
+                        }
+                    }
+                    Some(Err(e)) if !e.is_timeout() => return self.fail(index, e),
+                    _ => batch.mark_unfinished(),
+                }
+            }
+            Done::Channels { res, .. } => {
+                batch.pending -= 1;
+                match res {
+                    Some(Ok(channels)) => {
+                        let mut channels: HashMap<String, ChannelInformation> = channels
+                            .into_iter()
+                            .map(|c| (c.broadcaster_id.clone(), c))
+                            .collect();
+                        for user in batch.users.iter_mut().flatten() {
+                            user.channel = channels.remove(&user.id);
+                            if user.channel.as_ref().is_some_and(|c| c.tags.is_empty()) {
+                                batch.pending += 1;
+                                self.enrichment_jobs.push_back(Job::StreamTags {
+                                    batch: index,
+                                    id: user.id.clone(),
+                                });
+                            }
+                        }
+                    }
+                    Some(Err(e)) if !e.is_timeout() => return self.fail(index, e),
+                    _ => batch.mark_unfinished(),
+                }
+            }
+            Done::StreamTags { id, res, .. } => {
+                batch.pending -= 1;
+                let user = batch.users.iter_mut().flatten().find(|u| u.id == id);
+                if let Some(user) = user {
+                    match (res, user.channel.as_mut()) {
+                        (Some(tags), Some(channel)) => add_stream_tags(channel, tags),
+                        (None, _) => {
+                            batch.unfinished.insert(user.login.clone());
+                        }
+                        (_, None) => {}
+                    }
+                }
+            }
+        }
+        if self.batches[index].pending == 0 {
+            self.finish(index);
+        }
+        Ok(())
+    }
+
+    fn enrichment_jobs_for(&self, batch: usize, users: &[TwitchUserData]) -> Vec<Job> {
+        let enrichments = self.enrichments;
+        let mut jobs = vec![];
+        if enrichments.followers || enrichments.follower_lists {
+            jobs.extend(users.iter().map(|u| Job::Followers {
+                batch,
+                id: u.id.clone(),
+                lists: enrichments.follower_lists,
+            }));
+        }
+        if users.is_empty() {
+            return jobs;
+        }
+        if enrichments.streams {
+            let logins = users.iter().map(|u| u.login.clone()).collect();
+            jobs.push(Job::Streams { batch, logins });
+        }
+        if enrichments.channels {
+            let ids = users.iter().map(|u| u.id.clone()).collect();
+            jobs.push(Job::Channels { batch, ids });
+        }
+        jobs
+    }
+
+    fn finish(&mut self, index: usize) {
+        let batch = &mut self.batches[index];
+        batch.finished = true;
+        let users = batch.users.take();
+        let unfinished = match &users {
+            Some(users) => users
+                .iter()
+                .map(|u| &u.login)
+                .filter(|login| batch.unfinished.contains(*login))
+                .cloned()
+                .collect(),
+            None => batch.logins.clone(),
+        };
+        self.finished.push_back(FinishedBatch {
+            index,
+            users,
+            unfinished,
+        });
+    }
+
+    fn fail(&mut self, index: usize, e: Error) -> Result<(), Error> {
+        let batch = &mut self.batches[index];
+        batch.finished = true;
+        batch.users = None;
+        Err(e)
+    }
+}
+
+impl BatchState {
+    /// Marks the logins of all users unfinished.
+    fn mark_unfinished(&mut self) {
+        for user in self.users.iter().flatten() {
+            self.unfinished.insert(user.login.clone());
+        }
+    }
+}
+
+fn run_job(config: &Config, job: Job, deadline: Option<Instant>) -> BoxFuture<'_, Done> {
+    Box::pin(async move {
+        match job {
+            Job::Users {
+                batch,
+                url,
+                batch_size,
+            } => {
+                let users = get_data_for_twitch_users(config, &url, batch_size);
+                let res = until(deadline, users).await;
+                Done::Users { batch, res }
+            }
+            Job::Followers { batch, id, lists } => {
+                let lookup = get_follower_data(config, &id, lists)
+                    .instrument(info_span!("follower_lookup", user_id = id.as_str()));
+                let res = until(deadline, lookup).await;
+                Done::Followers { batch, id, res }
+            }
+            Job::Streams { batch, logins } => {
+                let streams = get_live_streams(config, logins.iter().collect());
+                let res = until(deadline, streams).await;
+                Done::Streams { batch, res }
+            }
+            Job::Channels { batch, ids } => {
+                let channels = get_channel_information(config, &ids);
+                let res = until(deadline, channels).await;
+                Done::Channels { batch, res }
+            }
+            Job::StreamTags { batch, id } => {
+                let res = until(deadline, get_stream_tags(config, &id)).await;
+                Done::StreamTags { batch, id, res }
+            }
+        }
+    })
+}
+
+/// Runs `future` until the crawl deadline, `None` if the deadline passed first.
+async fn until<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return Some(future.await),
+    };
+    let now = Instant::now();
+    if now >= deadline {
+        return None;
+    }
+    future::timeout(deadline - now, future).await.ok()
+}
+
+/// Counts the looked up logins and drops the unfinished ones from `missing`.
+fn record_logins(
+    config: &Config,
+    logins: usize,
+    missing: Vec<MissingLogin>,
+    unfinished: &[String],
+) -> Vec<MissingLogin> {
+    let not_found = missing.len();
+    let missing = without_unfinished(missing, unfinished);
+    config
+        .metrics
+        .record_logins(logins - not_found, missing.len(), not_found - missing.len());
+    missing
+}
+
+/// Logins without user are only missing if their lookup finished.
+fn without_unfinished(mut missing: Vec<MissingLogin>, unfinished: &[String]) -> Vec<MissingLogin> {
+    missing.retain(|m| !unfinished.iter().any(|u| u.eq_ignore_ascii_case(&m.login)));
+    missing
+}
+
+/// Logins in README order, each only once. Logins are case-insensitive.
+fn unique_logins<'a>(streamers: &[&'a Streamer]) -> Vec<&'a String> {
+    let mut seen = HashSet::new();
+    streamers
+        .iter()
+        .map(|s| s.login_name())
+        .filter(|login| seen.insert(login.to_lowercase()))
+        .collect()
+}
+
+/// Tags every user with the README name of the streamer that asked for
+/// it and returns the streamers without user, plus the logins listed
+/// more than once. Logins are case-insensitive.
+fn link_streamers(
+    users: &mut [TwitchUserData],
+    aliases: HashMap<String, usize>,
+    streamers: &[&Streamer],
+    policy: DuplicatePolicy,
+) -> (Vec<MissingLogin>, Vec<Diagnostic>) {
+    let mut positions = aliases;
+    for (pos, user) in users.iter().enumerate() {
+        positions.insert(user.login.to_lowercase(), pos);
+    }
+
+    // README names of every user, entries of a renamed user included
+    let mut names_by_user: Vec<Vec<String>> = vec![vec![]; users.len()];
+    let mut missing = vec![];
+    let mut diagnostics = vec![];
+    for entry in group_entries(streamers) {
+        let names: Vec<String> = entry.iter().map(|s| s.name().clone()).collect();
+        let login = entry[0].login_name();
+        match positions.get(&login.to_lowercase()) {
+            Some(&pos) => names_by_user[pos].extend(names.iter().cloned()),
+            None => missing.push(MissingLogin {
+                login: login.clone(),
+                name: entry_name(&names, policy),
+            }),
+        }
+        if names.len() > 1 {
+            diagnostics.push(Diagnostic::DuplicateEntry {
+                login: login.clone(),
+                names,
+            });
+        }
+    }
+    for (user, names) in users.iter_mut().zip(names_by_user) {
+        if !names.is_empty() {
+            user.streamer_name = entry_name(&names, policy);
+        }
+    }
+    (missing, diagnostics)
+}
+
+/// README entries grouped by case-insensitive login, in README order.
+fn group_entries<'a>(streamers: &[&'a Streamer]) -> Vec<Vec<&'a Streamer>> {
+    let mut entries: Vec<Vec<&Streamer>> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for streamer in streamers {
+        match positions.entry(streamer.login_name().to_lowercase()) {
+            Entry::Occupied(pos) => entries[*pos.get()].push(streamer),
+            Entry::Vacant(pos) => {
+                pos.insert(entries.len());
+                entries.push(vec![streamer]);
+            }
+        }
+    }
+    entries
+}
+
+/// Name of a user listed under all of `names`.
+fn entry_name(names: &[String], policy: DuplicatePolicy) -> String {
+    match policy {
+        DuplicatePolicy::KeepFirst => names[0].clone(),
+        DuplicatePolicy::KeepLast => names[names.len() - 1].clone(),
+        DuplicatePolicy::MergeNames => names.join(" / "),
+    }
+}
+
+#[tracing::instrument(name = "user_batch", skip(config, url), fields(url = %scrub_url(url)))]
+async fn get_data_for_twitch_users(
+    config: &Config,
+    url: &str,
+    batch_size: usize,
+) -> Result<Vec<TwitchUserData>, Error> {
+    let UsersData { users } = get_json(config, url).await?;
+    debug!(users = users.len(), "user batch received");
+    Ok(users)
+}
+
+async fn get_total_followers(config: &Config, url: &str) -> Result<TwitchFollowers, Error> {
+    get_json(config, url).await
+}
+
+/// Follower count of `user_id`, or every follower if `lists`.
+async fn get_follower_data(
+    config: &Config,
+    user_id: &str,
+    lists: bool,
+) -> Result<FollowerData, Error> {
+    if lists {
+        let listing = get_follower_listing(config, user_id).await?;
+        return Ok(FollowerData::List {
+            total: listing.total.unwrap_or(listing.items.len() as u32),
+            followers: listing.items,
+        });
+    }
+    let url = build_followers_url(&config.helix_url, user_id);
+    let followers = get_total_followers(config, &url).await?;
+    Ok(FollowerData::Total(followers.total))
+}
+
+/// Every follower of a user, newest follow first.
+pub async fn get_followers(config: &Config, user_id: &str) -> Result<Vec<Follower>, Error> {
+    Ok(get_follower_listing(config, user_id).await?.items)
 }
 
-pub async fn get_twitch_users(
+async fn get_follower_listing(config: &Config, user_id: &str) -> Result<Listing<Follower>, Error> {
+    let url = format!(
+        "{}/users/follows?to_id={}&first={}",
+        config.helix_url, user_id, PAGE_SIZE
+    );
+    get_pages(config, &url, usize::MAX).await
+}
+
+/// Past broadcasts and highlights of a user, newest first. Uploads are
+/// left out, they say nothing about how often someone streams.
+pub async fn get_videos(config: &Config, user_id: &str) -> Result<Vec<Video>, Error> {
+    let url = format!(
+        "{}/videos?user_id={}&type=all&first={}",
+        config.helix_url, user_id, PAGE_SIZE
+    );
+    let videos: Vec<Video> = get_all_pages(config, &url).await?;
+    Ok(videos
+        .into_iter()
+        .filter(|v| v.video_type == VideoType::Archive || v.video_type == VideoType::Highlight)
+        .collect())
+}
+
+/// Streams of the given logins that are live right now, offline logins
+/// are not part of the result.
+pub async fn get_live_streams(
+    config: &Config,
     login_names: Vec<&String>,
-) -> Result<Vec<TwitchUserData>, AsyncError> {
-    let urls = build_users_urls(&login_names, LOGIN_CHUNK_SIZE);
+) -> Result<Vec<LiveStream>, Error> {
+    let urls = build_batched_urls(
+        &format!("{}/streams", config.helix_url),
+        "user_login",
+        &login_names,
+        LOGIN_CHUNK_SIZE,
+    );
     let mut res = vec![];
     for url in urls {
-        res.extend(get_data_for_twitch_users(&url).await?);
+        res.extend(get_all_pages(config, &format!("{}&first={}", url, PAGE_SIZE)).await?);
     }
     Ok(res)
 }
 
-pub async fn get_twitch_users_parallel(
-    login_names: Vec<&String>,
-) -> Result<HashMap<String, TwitchUserData>, AsyncError> {
-    let streamers_mutex = Arc::new(Mutex::new(HashMap::new()));
-    let (streamer_sender, streamer_receiver) = mpsc::unbounded();
-    let receiver_handle = task::spawn(streamer_received_loop(
-        streamer_receiver,
-        streamers_mutex.clone(),
-    ));
-    let urls = build_users_urls(&login_names, LOGIN_CHUNK_SIZE / 2);
-    //println!("urls: {}, login_names: {}", urls.len(), login_names.len());
-    for chunk in urls.chunks(4) {
+/// Most viewed clips of a broadcaster created between `started_at` and
+/// `ended_at`, at most `limit` of them.
+pub async fn get_top_clips(
+    config: &Config,
+    broadcaster_id: &str,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    limit: usize,
+) -> Result<Vec<Clip>, Error> {
+    let url = format!(
+        "{}/clips?broadcaster_id={}&started_at={}&ended_at={}&first={}",
+        config.helix_url,
+        broadcaster_id,
+        started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        ended_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        limit.clamp(1, PAGE_SIZE)
+    );
+    let mut clips: Vec<Clip> = get_pages(config, &url, limit).await?.items;
+    clips.sort_by_key(|c| Reverse(c.view_count));
+    Ok(clips)
+}
+
+/// Top clips of several broadcasters in the same time window, merged into
+/// one list ordered by view count, e.g. for a best of the week digest.
+pub async fn get_top_clips_for_broadcasters(
+    config: &Config,
+    broadcaster_ids: Vec<&String>,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    limit_per_broadcaster: usize,
+) -> Result<Vec<Clip>, Error> {
+    let mut clips = vec![];
+    for chunk in broadcaster_ids.chunks(4) {
         let mut workers = vec![];
-        for url in chunk {
-            workers.push(get_and_send_users_data(&url, streamer_sender.clone()));
+        for id in chunk {
+            workers.push(get_top_clips(
+                config,
+                id,
+                started_at,
+                ended_at,
+                limit_per_broadcaster,
+            ));
+        }
+        for res in join_all(workers).await {
+            clips.extend(res?);
         }
-        let _res = join_all(workers).await;
-        //println!("{:?}", res);
     }
-    drop(streamer_sender);
-    receiver_handle.await?;
+    clips.sort_by_key(|c| Reverse(c.view_count));
+    Ok(clips)
+}
+
+/// Channel information including tags for the given broadcasters. Channels
+/// without tags get their stream tags, a failed lookup leaves them empty.
+pub async fn get_channels(
+    config: &Config,
+    broadcaster_ids: Vec<&String>,
+) -> Result<Vec<ChannelInformation>, Error> {
+    let ids: Vec<String> = broadcaster_ids.into_iter().cloned().collect();
+    let mut channels = get_channel_information(config, &ids).await?;
+    // one after another, the users pipeline runs them as jobs of their own
+    for channel in channels.iter_mut().filter(|c| c.tags.is_empty()) {
+        let tags = get_stream_tags(config, &channel.broadcaster_id).await;
+        add_stream_tags(channel, tags);
+    }
+    Ok(channels)
+}
 
-    add_followers(streamers_mutex.clone()).await?;
+/// Channel information as `/helix/channels` sends it.
+async fn get_channel_information(
+    config: &Config,
+    broadcaster_ids: &[String],
+) -> Result<Vec<ChannelInformation>, Error> {
+    let broadcaster_ids: Vec<&String> = broadcaster_ids.iter().collect();
+    let urls = build_batched_urls(
+        &format!("{}/channels", config.helix_url),
+        "broadcaster_id",
+        &broadcaster_ids,
+        LOGIN_CHUNK_SIZE,
+    );
+    let mut channels: Vec<ChannelInformation> = vec![];
+    for url in urls {
+        let Page { data, .. } = get_json(config, &url).await?;
+        channels.extend(data);
+    }
+    Ok(channels)
+}
 
-    //println!("entries: {:?}", streamers_mutex.lock().await);
+fn add_stream_tags(channel: &mut ChannelInformation, tags: Result<Vec<String>, Error>) {
+    match tags {
+        Ok(tags) => channel.tags = tags,
+        Err(e) => warn!(
+            broadcaster_id = channel.broadcaster_id.as_str(),
+            error = %e,
+            "stream tags lookup failed"
+        ),
+    }
+}
 
-    //let streamers = &*streamers_mutex.lock().await;
-    Ok(Arc::try_unwrap(streamers_mutex).unwrap().into_inner())
+/// Stream tags are deprecated by Twitch, only for channels without tags.
+async fn get_stream_tags(config: &Config, broadcaster_id: &str) -> Result<Vec<String>, Error> {
+    let url = format!(
+        "{}/streams/tags?broadcaster_id={}",
+        config.helix_url, broadcaster_id
+    );
+    let tags: Vec<StreamTag> = get_all_pages(config, &url).await?;
+    Ok(tags
+        .into_iter()
+        .filter_map(|mut tag| tag.localization_names.remove("en-us"))
+        .collect())
 }
 
-async fn streamer_received_loop(
-    mut events: Receiver<Vec<TwitchUserData>>,
-    streamers: Arc<Mutex<HashMap<String, TwitchUserData>>>,
-) -> Result<(), AsyncError> {
-    let mut streamers = streamers.lock().await;
-    while let Some(event) = events.next().await {
-        for streamer in event {
-            match streamers.entry(streamer.id.clone()) {
-                Entry::Occupied(_) => panic!("streamer {:?} already in map", streamer.id),
-                Entry::Vacant(entry) => entry.insert(streamer),
-            };
+/// Stream schedule of a broadcaster with all upcoming segments, `None`
+/// for broadcasters without a schedule.
+pub async fn get_schedule(
+    config: &Config,
+    broadcaster_id: &str,
+) -> Result<Option<Schedule>, Error> {
+    let url = format!(
+        "{}/schedule?broadcaster_id={}&first=25",
+        config.helix_url, broadcaster_id
+    );
+    let mut schedule: Option<Schedule> = None;
+    let mut cursor: Option<String> = None;
+    loop {
+        let page_url = match &cursor {
+            Some(cursor) => format!("{}&after={}", url, cursor),
+            None => url.clone(),
+        };
+        let res = get_with_retry(config, &page_url).await?;
+        // Helix answers 404 when there is no schedule or no segment left
+        if res.status == 404 {
+            return Ok(schedule);
+        }
+        if !(200..300).contains(&res.status) {
+            return Err(status_error(&page_url, &res));
+        }
+        let ScheduleData { data, pagination } =
+            serde_json::from_str(&res.body).map_err(|e| Error::decode(&page_url, &res.body, e))?;
+        let last_page = data.segments.is_empty();
+        match schedule.as_mut() {
+            Some(schedule) => schedule.segments.extend(data.segments),
+            None => schedule = Some(data),
+        }
+        match pagination.cursor {
+            Some(next) if !last_page => cursor = Some(next),
+            _ => return Ok(schedule),
+        }
+    }
+}
+
+/// Schedules of several broadcasters, broadcasters without one are left out.
+pub async fn get_schedules(
+    config: &Config,
+    broadcaster_ids: Vec<&String>,
+) -> Result<Vec<Schedule>, Error> {
+    let mut schedules = vec![];
+    for chunk in broadcaster_ids.chunks(4) {
+        let mut workers = vec![];
+        for id in chunk {
+            workers.push(get_schedule(config, id));
+        }
+        for res in join_all(workers).await {
+            schedules.extend(res?);
         }
     }
-    print!("all events received");
-    Ok(())
+    Ok(schedules)
 }
 
-async fn get_and_send_users_data(
-    url: &String,
-    mut broker: Sender<Vec<TwitchUserData>>,
-) -> Result<(), AsyncError> {
-    let data = get_data_for_twitch_users(url).await?;
-    broker.send(data).await?;
-    Ok(())
+/// Follows the pagination cursor of `url` until the last page.
+async fn get_all_pages<T: DeserializeOwned>(config: &Config, url: &str) -> Result<Vec<T>, Error> {
+    Ok(get_pages(config, url, usize::MAX).await?.items)
 }
 
-async fn get_data_for_twitch_users(url: &String) -> Result<Vec<TwitchUserData>, AsyncError> {
-    let client_id = match std::env::var(CLIENT_ID_KEY) {
-        Ok(val) => val,
-        Err(e) => panic!("twich client id not found, key {}, {}", CLIENT_ID_KEY, e),
+/// Follows the pagination cursor of `url` until `limit` items are received.
+async fn get_pages<T: DeserializeOwned>(
+    config: &Config,
+    url: &str,
+    limit: usize,
+) -> Result<Listing<T>, Error> {
+    let mut res = Listing {
+        items: vec![],
+        total: None,
     };
-    let bearer_token = match std::env::var(BEARER_TOKEN_KEY) {
-        Ok(val) => format!("Bearer {}", val),
-        Err(e) => panic!(
-            "twich bearer token not found, key {}, {}",
-            BEARER_TOKEN_KEY, e
-        ),
+    let mut cursor: Option<String> = None;
+    loop {
+        let page_url = match &cursor {
+            Some(cursor) => format!("{}&after={}", url, cursor),
+            None => url.to_string(),
+        };
+        let page: Page<T> = get_json(config, &page_url).await?;
+        let last_page = page.data.is_empty();
+        if cursor.is_none() {
+            res.total = page.total;
+        }
+        res.items.extend(page.data);
+        if res.items.len() >= limit {
+            res.items.truncate(limit);
+            return Ok(res);
+        }
+        match page.pagination.cursor {
+            Some(next) if !last_page => cursor = Some(next),
+            _ => return Ok(res),
+        }
+    }
+}
+
+/// Authorized GET against Helix, retrying 429 and 5xx responses.
+async fn get_json<T: DeserializeOwned>(config: &Config, url: &str) -> Result<T, Error> {
+    let res = get_with_retry(config, url).await?;
+    if !(200..300).contains(&res.status) {
+        return Err(status_error(url, &res));
+    }
+    serde_json::from_str(&res.body).map_err(|e| Error::decode(url, &res.body, e))
+}
+
+async fn get_with_retry(config: &Config, url: &str) -> Result<Interaction, Error> {
+    let mut attempt = 0;
+    loop {
+        let res = send(config, "GET", url, true, vec![], None).await?;
+        if (res.status == 429 || res.status >= 500) && attempt < config.max_retries {
+            let delay = if res.status == 429 {
+                let wait = rate_limit_wait(&res).max(config.retry_backoff);
+                config.metrics.record_rate_limit_wait(wait);
+                wait
+            } else {
+                config.retry_backoff * 2u32.pow(attempt)
+            };
+            attempt += 1;
+            warn!(
+                url = %scrub_url(url),
+                status = res.status,
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                "retrying"
+            );
+            if let Traffic::Live | Traffic::Record(_) = config.traffic {
+                task::sleep(delay).await;
+            }
+            continue;
+        }
+        return Ok(res);
+    }
+}
+
+/// Authorized POST of a JSON body against Helix.
+pub(crate) async fn post_json(
+    config: &Config,
+    url: &str,
+    body: &serde_json::Value,
+) -> Result<Interaction, Error> {
+    send(config, "POST", url, true, vec![], Some(body)).await
+}
+
+/// Unauthorized GET with extra request headers, e.g. `If-None-Match`.
+pub(crate) async fn get_with_headers(
+    config: &Config,
+    url: &str,
+    headers: Vec<(String, String)>,
+) -> Result<Interaction, Error> {
+    send(config, "GET", url, false, headers, None).await
+}
+
+/// Sends one request, or takes its response from the replayed cassette.
+/// Request bodies are not recorded, they may contain secrets.
+async fn send(
+    config: &Config,
+    method: &str,
+    url: &str,
+    authorized: bool,
+    headers: Vec<(String, String)>,
+    body: Option<&serde_json::Value>,
+) -> Result<Interaction, Error> {
+    if let Traffic::Replay(player) = &config.traffic {
+        return player.play(method, url);
+    }
+
+    let mut request_headers = headers;
+    if authorized {
+        request_headers.push((
+            "Authorization".to_string(),
+            format!("Bearer {}", config.bearer_token),
+        ));
+        request_headers.push(("Client-ID".to_string(), config.client_id.clone()));
+    }
+    let request = HttpRequest {
+        method: method.to_string(),
+        url: url.to_string(),
+        headers: request_headers,
+        body: body.map(|body| body.to_string()),
     };
-    //println!("{}", url);
-    let UsersData { users } = surf::get(url)
-        .set_header("Authorization".parse().unwrap(), bearer_token)
-        .set_header("Client-ID".parse().unwrap(), client_id)
-        .recv_json()
-        .await?;
+    transfer(config, request, false).await
+}
 
-    Ok(users)
+/// GET of a binary file like an image, recorded and replayed like the API
+/// requests with the body hex encoded.
+pub async fn get_bytes(
+    config: &Config,
+    url: &str,
+    headers: Vec<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let mut request = HttpRequest::get(url);
+    request.headers = headers;
+    let interaction = transfer(config, request, true).await?;
+    Ok(HttpResponse {
+        status: interaction.status,
+        body: interaction.body_bytes()?,
+        headers: interaction.response_headers,
+    })
 }
 
-async fn add_followers(
-    streamers_mutex: Arc<Mutex<HashMap<String, TwitchUserData>>>,
-) -> Result<(), AsyncError> {
-    println!("adding followers");
-    let streamers = streamers_mutex.lock().await;
-    println!("{} streamers", streamers.len());
-    let follower_urls: Vec<(String, String)> = streamers
+/// Exchanges `request` with the server or the replayed cassette, and
+/// records the interaction. A `binary` body is kept hex encoded.
+async fn transfer(
+    config: &Config,
+    request: HttpRequest,
+    binary: bool,
+) -> Result<Interaction, Error> {
+    if let Traffic::Replay(player) = &config.traffic {
+        return player.play(&request.method, &request.url);
+    }
+
+    let (method, url) = (request.method.clone(), request.url.clone());
+    let request_headers = request.headers.clone();
+    let endpoint = if binary {
+        metrics::IMAGE_ENDPOINT.to_string()
+    } else {
+        metrics::endpoint(&url)
+    };
+    let res = exchange(config, request, &endpoint).await?;
+    let response_headers = RECORDED_HEADERS
         .iter()
-        .take(20)
-        .map(|(k, _)| (build_followers_url(k), k.clone()))
+        .filter_map(|name| {
+            res.header(name)
+                .map(|value| (name.to_string(), value.to_string()))
+        })
         .collect();
-    drop(streamers);
-    println!("{} followers waiting", follower_urls.len());
-
-    let (followers_sender, followers_receiver) = mpsc::unbounded();
-    let receiver_handle = task::spawn(followers_received_loop(
-        followers_receiver,
-        streamers_mutex.clone(),
-    ));
+    let body = if binary {
+        hex::encode(&res.body)
+    } else {
+        res.text()
+    };
+    let interaction = Interaction {
+        method,
+        url,
+        request_headers,
+        status: res.status,
+        response_headers,
+        body,
+        binary,
+    };
 
-    for chunk in follower_urls.chunks(4) {
-        let mut workers = vec![];
-        for (url, id) in chunk {
-            workers.push(get_and_send_followers(&url, &id, followers_sender.clone()));
-        }
-        let _res = join_all(workers).await;
-        //println!("{:?}", res);
+    if let Traffic::Record(recorder) = &config.traffic {
+        recorder.record(interaction.clone());
     }
+    Ok(interaction)
+}
 
-    drop(followers_sender);
-    receiver_handle.await?;
-    Ok(())
+/// Sends `request` through the transport with the request timeout, and
+/// logs and counts the response under `endpoint`.
+async fn exchange(
+    config: &Config,
+    request: HttpRequest,
+    endpoint: &str,
+) -> Result<HttpResponse, Error> {
+    let (method, url) = (request.method.clone(), request.url.clone());
+    let (method, url) = (method.as_str(), url.as_str());
+    let started = Instant::now();
+    let res = future::timeout(config.request_timeout, config.transport.send(request))
+        .await
+        .map_err(|_| Error::Timeout {
+            url: url.to_string(),
+            timeout: config.request_timeout,
+        })
+        .and_then(|res| res)
+        .map_err(|e| {
+            let status = if e.is_timeout() { "timeout" } else { "error" };
+            config
+                .metrics
+                .record_request(endpoint, status, started.elapsed());
+            warn!(method, url = %scrub_url(url), error = %e, "request failed");
+            e
+        })?;
+    config
+        .metrics
+        .record_request(endpoint, &res.status.to_string(), started.elapsed());
+    debug!(
+        method,
+        url = %scrub_url(url),
+        status = res.status,
+        latency_ms = started.elapsed().as_millis() as u64,
+        "response"
+    );
+    Ok(res)
 }
 
-async fn followers_received_loop(
-    mut events: Receiver<TwitchFollowers>,
-    streamers: Arc<Mutex<HashMap<String, TwitchUserData>>>,
-) -> Result<(), AsyncError> {
-    println!("starting receiver");
-    let mut streamers = streamers.lock().await;
-    while let Some(followers) = events.next().await {
-        match streamers.entry(followers.id.clone()) {
-            Entry::Occupied(mut entry) => {
-                let tu = &mut *entry.get_mut();
-                tu.set_follower_count(followers.total);
-            }
-            Entry::Vacant(_) => panic!("streamer {:?} not found", followers.id),
-        };
+/// Error for a response with an unexpected status.
+pub(crate) fn status_error(url: &str, res: &Interaction) -> Error {
+    match res.status {
+        401 | 403 => Error::Auth {
+            status: res.status,
+            url: url.to_string(),
+            message: error_message(&res.body),
+        },
+        429 => Error::RateLimited {
+            url: url.to_string(),
+            reset: rate_limit_reset(res)
+                .and_then(|reset| Utc.timestamp_opt(reset as i64, 0).single()),
+        },
+        status => Error::Http {
+            status,
+            url: url.to_string(),
+        },
     }
-    Ok(())
 }
 
-async fn get_and_send_followers(
-    url: &String,
-    id: &String,
-    mut broker: Sender<TwitchFollowers>,
-) -> Result<(), AsyncError> {
-    let mut follower = get_total_followers(url).await?;
-    follower.id = id.clone();
-    broker.send(follower).await?;
-    Ok(())
+/// `message` of a Twitch error body like
+/// `{"error":"Unauthorized","status":401,"message":"Invalid OAuth token"}`.
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .unwrap_or_default()
 }
 
-async fn get_total_followers(url: &String) -> Result<TwitchFollowers, AsyncError> {
-    let client_id = match std::env::var(CLIENT_ID_KEY) {
-        Ok(val) => val,
-        Err(e) => panic!("twich client id not found, key {}, {}", CLIENT_ID_KEY, e),
-    };
-    let bearer_token = match std::env::var(BEARER_TOKEN_KEY) {
-        Ok(val) => format!("Bearer {}", val),
-        Err(e) => panic!(
-            "twich bearer token not found, key {}, {}",
-            BEARER_TOKEN_KEY, e
-        ),
-    };
-    //println!("{}", url);
-    let follower: TwitchFollowers = surf::get(url)
-        .set_header("Authorization".parse().unwrap(), bearer_token)
-        .set_header("Client-ID".parse().unwrap(), client_id)
-        .recv_json()
-        .await?;
+/// Unix time the rate limit bucket refills, from the `Ratelimit-Reset` header.
+fn rate_limit_reset(res: &Interaction) -> Option<u64> {
+    res.response_header("Ratelimit-Reset")
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
+/// Time until the rate limit bucket refills.
+fn rate_limit_wait(res: &Interaction) -> Duration {
+    let reset = rate_limit_reset(res);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    match reset {
+        Some(reset) if reset > now => Duration::from_secs(reset - now).min(MAX_RATE_LIMIT_WAIT),
+        _ => Duration::from_secs(0),
+    }
+}
 
-    Ok(follower)
+fn build_users_urls(helix_url: &str, login_names: &[&String], chunk_size: usize) -> Vec<String> {
+    build_batched_urls(
+        &format!("{}/users", helix_url),
+        "login",
+        login_names,
+        chunk_size,
+    )
 }
 
-fn build_users_urls(login_names: &Vec<&String>, chunk_size: usize) -> Vec<String> {
-    login_names
+/// One url per chunk of `values`, each value as a repeated `key` parameter.
+fn build_batched_urls(
+    endpoint: &str,
+    key: &str,
+    values: &[&String],
+    chunk_size: usize,
+) -> Vec<String> {
+    values
         .chunks(chunk_size)
-        .map(|streamer_chunk| {
-            streamer_chunk
+        .map(|chunk| {
+            chunk
                 .iter()
                 .enumerate()
-                .map(|(i, login_name)| {
-                    let fmt = if i == 0 {
-                        "https://api.twitch.tv/helix/users?login="
+                .map(|(i, value)| {
+                    if i == 0 {
+                        format!("{}?{}={}", endpoint, key, value)
                     } else {
-                        "&login="
-                    };
-                    format!("{}{}", fmt, login_name)
+                        format!("&{}={}", key, value)
+                    }
                 })
                 .collect()
         })
         .collect()
 }
 
-fn build_followers_url(user_id: &String) -> String {
-    format!(
-        "https://api.twitch.tv/helix/users/follows?to_id={}&first=1",
-        user_id
-    )
+fn parse_video_duration(duration: &str) -> Duration {
+    let mut seconds = 0;
+    let mut number = 0;
+    for c in duration.chars() {
+        match c {
+            '0'..='9' => number = number * 10 + c.to_digit(10).unwrap() as u64,
+            'h' => seconds += number * 3600,
+            'm' => seconds += number * 60,
+            's' => seconds += number,
+            _ => {}
+        }
+        if !c.is_ascii_digit() {
+            number = 0;
+        }
+    }
+    Duration::from_secs(seconds)
+}
+
+fn build_followers_url(helix_url: &str, user_id: &str) -> String {
+    format!("{}/users/follows?to_id={}&first=1", helix_url, user_id)
 }
 
 #[cfg(test)]
@@ -257,9 +2180,13 @@ mod tests {
 
     #[test]
     fn test_build_urls() {
-        let login_names = vec!["s_1".to_string(), "s_2".to_string(), "s_3".to_string()];
+        let login_names = ["s_1".to_string(), "s_2".to_string(), "s_3".to_string()];
 
-        let urls = build_users_urls(&login_names.iter().collect(), 2);
+        let urls = build_users_urls(
+            "https://api.twitch.tv/helix",
+            &login_names.iter().collect::<Vec<_>>(),
+            2,
+        );
 
         assert_eq!(
             urls,
@@ -269,4 +2196,11 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_parse_video_duration() {
+        assert_eq!(parse_video_duration("3h8m33s"), Duration::from_secs(11313));
+        assert_eq!(parse_video_duration("45m0s"), Duration::from_secs(2700));
+        assert_eq!(parse_video_duration("12s"), Duration::from_secs(12));
+    }
 }
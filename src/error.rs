@@ -0,0 +1,212 @@
+//! Errors of the Twitch and list downloads. Callers can tell a request
+//! Twitch refused from a response that didn't decode and retry or alert
+//! accordingly.
+use chrono::{DateTime, Utc};
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Duration;
+
+/// Bytes of a body kept in [`Error::Decode`].
+const EXCERPT_LEN: usize = 200;
+
+type BoxError = Box<dyn StdError + Send + Sync + 'static>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Response with a status no other variant covers.
+    Http {
+        status: u16,
+        url: String,
+    },
+    /// 429 that was still there after all retries. `reset` is when the
+    /// rate limit bucket refills, if Twitch said so.
+    RateLimited {
+        url: String,
+        reset: Option<DateTime<Utc>>,
+    },
+    /// 401 or 403, e.g. an expired token or a wrong client id.
+    Auth {
+        status: u16,
+        url: String,
+        message: String,
+    },
+    /// Body that isn't the expected JSON. `url` may also be a file path.
+    Decode {
+        url: String,
+        excerpt: String,
+        source: serde_json::Error,
+    },
+    /// The streamer list isn't in the expected markdown format. `offset`
+    /// is the byte where parsing stopped, if it got that far.
+    Parse {
+        message: String,
+        offset: Option<usize>,
+        source: Option<BoxError>,
+    },
+    /// Missing or invalid settings or arguments.
+    Config {
+        message: String,
+        source: Option<BoxError>,
+    },
+    Io(std::io::Error),
+    /// No response within the configured request timeout.
+    Timeout {
+        url: String,
+        timeout: Duration,
+    },
+    /// The request got no response at all, e.g. connection refused, or no
+    /// recorded response when replaying.
+    Transport {
+        url: String,
+        source: BoxError,
+    },
+}
+
+impl Error {
+    pub fn config(message: impl Into<String>) -> Self {
+        Error::Config {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn transport(url: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Error::Transport {
+            url: url.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn decode(url: impl Into<String>, body: &str, source: serde_json::Error) -> Self {
+        let mut end = body.len().min(EXCERPT_LEN);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        Error::Decode {
+            url: url.into(),
+            excerpt: body[..end].to_string(),
+            source,
+        }
+    }
+
+    /// HTTP status of the response that caused the error.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Http { status, .. } | Error::Auth { status, .. } => Some(*status),
+            Error::RateLimited { .. } => Some(429),
+            _ => None,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout { .. })
+    }
+}
+
+/// Urls without query, it may contain the client secret.
+fn display_url(url: &str) -> &str {
+    url.split('?').next().unwrap_or_default()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Http { status, url } => write!(
+                f,
+                "request {} failed with status {}",
+                display_url(url),
+                status
+            ),
+            Error::RateLimited { url, reset } => {
+                write!(f, "request {} was rate limited", display_url(url))?;
+                match reset {
+                    Some(reset) => write!(f, " until {}", reset.to_rfc3339()),
+                    None => Ok(()),
+                }
+            }
+            Error::Auth {
+                status,
+                url,
+                message,
+            } => write!(
+                f,
+                "request {} was not authorized, status {}: {}",
+                display_url(url),
+                status,
+                message
+            ),
+            Error::Decode { url, excerpt, .. } => write!(
+                f,
+                "unexpected response from {}: {}",
+                display_url(url),
+                excerpt
+            ),
+            Error::Parse {
+                message,
+                offset: Some(offset),
+                ..
+            } => write!(
+                f,
+                "streamer list not parsed at byte {}: {}",
+                offset, message
+            ),
+            Error::Parse { message, .. } => write!(f, "streamer list not parsed: {}", message),
+            Error::Config { message, .. } => write!(f, "{}", message),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Timeout { url, timeout } => write!(
+                f,
+                "request {} timed out after {:?}",
+                display_url(url),
+                timeout
+            ),
+            Error::Transport { url, source } => {
+                write!(f, "request {} failed: {}", display_url(url), source)
+            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Decode { source, .. } => Some(source),
+            Error::Config {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            Error::Parse {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            Error::Io(e) => Some(e),
+            Error::Transport { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_error() {
+        let body = format!("<p>{}</p>", "ü".repeat(200));
+        let source = serde_json::from_str::<serde_json::Value>(&body).unwrap_err();
+
+        let error = Error::decode("https://example.com/users?client_secret=s", &body, source);
+
+        match &error {
+            Error::Decode { excerpt, .. } => assert_eq!(excerpt.len(), 199),
+            _ => panic!("not a decode error"),
+        }
+        assert!(error.source().is_some());
+        assert!(!error.to_string().contains("client_secret"));
+    }
+}
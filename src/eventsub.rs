@@ -0,0 +1,451 @@
+//! EventSub webhooks: creates the stream subscriptions and runs a local
+//! listener that verifies the notifications Twitch posts to the callback.
+use crate::downloader::{self, Config};
+use crate::error::Error;
+use crate::server::{self, Request, Response};
+use crate::AsyncError;
+use async_std::{
+    net::{TcpListener, ToSocketAddrs},
+    task,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::channel::mpsc;
+use futures::future::{abortable, AbortHandle};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+type Receiver<T> = mpsc::UnboundedReceiver<T>;
+type Sender<T> = mpsc::UnboundedSender<T>;
+
+/// Subscription types created for every broadcaster.
+pub const SUBSCRIPTION_TYPES: [&str; 3] = ["stream.online", "stream.offline", "channel.update"];
+const MESSAGE_ID: &str = "Twitch-Eventsub-Message-Id";
+const MESSAGE_TIMESTAMP: &str = "Twitch-Eventsub-Message-Timestamp";
+const MESSAGE_SIGNATURE: &str = "Twitch-Eventsub-Message-Signature";
+const MESSAGE_TYPE: &str = "Twitch-Eventsub-Message-Type";
+/// Twitch recommends rejecting messages older than ten minutes.
+const MAX_MESSAGE_AGE_MINUTES: i64 = 10;
+/// Message ids remembered for deduplication, Twitch retries a few times at most.
+const SEEN_MESSAGE_IDS: usize = 1000;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Subscription {
+    id: String,
+    status: String,
+    #[serde(rename = "type")]
+    subscription_type: String,
+    condition: Condition,
+}
+
+impl Subscription {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn status(&self) -> &String {
+        &self.status
+    }
+
+    pub fn subscription_type(&self) -> &String {
+        &self.subscription_type
+    }
+
+    pub fn broadcaster_user_id(&self) -> &String {
+        &self.condition.broadcaster_user_id
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct Condition {
+    broadcaster_user_id: String,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionData {
+    data: Vec<Subscription>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct StreamOnline {
+    id: String,
+    broadcaster_user_id: String,
+    broadcaster_user_login: String,
+    broadcaster_user_name: String,
+    #[serde(rename = "type")]
+    stream_type: String,
+    started_at: DateTime<Utc>,
+}
+
+impl StreamOnline {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn broadcaster_user_id(&self) -> &String {
+        &self.broadcaster_user_id
+    }
+
+    pub fn broadcaster_user_login(&self) -> &String {
+        &self.broadcaster_user_login
+    }
+
+    pub fn broadcaster_user_name(&self) -> &String {
+        &self.broadcaster_user_name
+    }
+
+    /// `live`, `playlist`, `watch_party`, `premiere` or `rerun`.
+    pub fn stream_type(&self) -> &String {
+        &self.stream_type
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct StreamOffline {
+    broadcaster_user_id: String,
+    broadcaster_user_login: String,
+    broadcaster_user_name: String,
+}
+
+impl StreamOffline {
+    pub fn broadcaster_user_id(&self) -> &String {
+        &self.broadcaster_user_id
+    }
+
+    pub fn broadcaster_user_login(&self) -> &String {
+        &self.broadcaster_user_login
+    }
+
+    pub fn broadcaster_user_name(&self) -> &String {
+        &self.broadcaster_user_name
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelUpdate {
+    broadcaster_user_id: String,
+    broadcaster_user_login: String,
+    broadcaster_user_name: String,
+    title: String,
+    language: String,
+    category_id: String,
+    category_name: String,
+}
+
+impl ChannelUpdate {
+    pub fn broadcaster_user_id(&self) -> &String {
+        &self.broadcaster_user_id
+    }
+
+    pub fn broadcaster_user_login(&self) -> &String {
+        &self.broadcaster_user_login
+    }
+
+    pub fn broadcaster_user_name(&self) -> &String {
+        &self.broadcaster_user_name
+    }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    pub fn language(&self) -> &String {
+        &self.language
+    }
+
+    pub fn category_id(&self) -> &String {
+        &self.category_id
+    }
+
+    pub fn category_name(&self) -> &String {
+        &self.category_name
+    }
+}
+
+/// Verified notification received by the [`WebhookListener`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    StreamOnline(StreamOnline),
+    StreamOffline(StreamOffline),
+    ChannelUpdate(ChannelUpdate),
+    /// Twitch revoked the subscription, e.g. because the user was banned.
+    Revoked(Subscription),
+}
+
+/// Creates the [`SUBSCRIPTION_TYPES`] subscriptions for every broadcaster.
+/// Twitch only accepts app access tokens here, see
+/// `Config::with_app_access_token`. Subscriptions that already exist
+/// are skipped, so this can be run again after a restart.
+pub async fn create_subscriptions(
+    config: &Config,
+    broadcaster_ids: Vec<&String>,
+    callback: &str,
+    secret: &str,
+) -> Result<Vec<Subscription>, Error> {
+    if !(10..=100).contains(&secret.len()) {
+        return Err(Error::config(
+            "eventsub secret must be between 10 and 100 characters",
+        ));
+    }
+    let url = format!("{}/eventsub/subscriptions", config.helix_url());
+    let mut subscriptions = vec![];
+    for broadcaster_id in broadcaster_ids {
+        for subscription_type in SUBSCRIPTION_TYPES.iter() {
+            let body = json!({
+                "type": subscription_type,
+                "version": "1",
+                "condition": { "broadcaster_user_id": broadcaster_id },
+                "transport": {
+                    "method": "webhook",
+                    "callback": callback,
+                    "secret": secret,
+                },
+            });
+            let res = downloader::post_json(config, &url, &body).await?;
+            match res.status {
+                409 => continue,
+                200..=299 => {
+                    let created: SubscriptionData = serde_json::from_str(&res.body)
+                        .map_err(|e| Error::decode(&url, &res.body, e))?;
+                    subscriptions.extend(created.data);
+                }
+                _ => return Err(downloader::status_error(&url, &res)),
+            }
+        }
+    }
+    Ok(subscriptions)
+}
+
+/// Value of the `Twitch-Eventsub-Message-Signature` header for a message.
+pub fn signature(secret: &str, message_id: &str, timestamp: &str, body: &[u8]) -> String {
+    let mac = message_mac(secret, message_id, timestamp, body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn message_mac(secret: &str, message_id: &str, timestamp: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(message_id.as_bytes());
+    mac.update(timestamp.as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// Local HTTP endpoint for the EventSub callback. Runs until dropped.
+pub struct WebhookListener {
+    addr: SocketAddr,
+    abort: AbortHandle,
+}
+
+impl WebhookListener {
+    /// Starts listening on `addr`, verified events are sent to the receiver.
+    pub async fn bind(
+        addr: impl ToSocketAddrs,
+        secret: &str,
+    ) -> Result<(WebhookListener, Receiver<Event>), AsyncError> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let (sender, receiver) = mpsc::unbounded();
+        let handler_state = Arc::new(ListenerState {
+            secret: secret.to_string(),
+            sender,
+            seen: Mutex::new(SeenMessages::default()),
+        });
+
+        let (serve, abort) = abortable(server::serve(listener, move |request| {
+            let state = handler_state.clone();
+            async move { handle(&request, &state) }
+        }));
+        task::spawn(serve);
+
+        Ok((WebhookListener { addr, abort }, receiver))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for WebhookListener {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+struct ListenerState {
+    secret: String,
+    sender: Sender<Event>,
+    seen: Mutex<SeenMessages>,
+}
+
+/// Most recent message ids, oldest evicted first.
+#[derive(Default)]
+struct SeenMessages {
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+}
+
+impl SeenMessages {
+    /// Remembers `id`, false if it was seen before.
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        if self.order.len() > SEEN_MESSAGE_IDS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+fn handle(request: &Request, state: &ListenerState) -> Response {
+    if request.method != "POST" {
+        return Response::new(404);
+    }
+    let (id, timestamp, signature_header) = match (
+        request.header(MESSAGE_ID),
+        request.header(MESSAGE_TIMESTAMP),
+        request.header(MESSAGE_SIGNATURE),
+    ) {
+        (Some(id), Some(timestamp), Some(signature)) => (id, timestamp, signature),
+        _ => return Response::new(400),
+    };
+    if !verify(
+        &state.secret,
+        id,
+        timestamp,
+        &request.body,
+        signature_header,
+    ) {
+        return Response::new(403);
+    }
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(sent)
+            if Utc::now() - sent.with_timezone(&Utc)
+                <= Duration::minutes(MAX_MESSAGE_AGE_MINUTES) => {}
+        _ => return Response::new(403),
+    }
+    let message: Value = match serde_json::from_slice(&request.body) {
+        Ok(message) => message,
+        Err(_) => return Response::new(400),
+    };
+
+    if request.header(MESSAGE_TYPE) == Some("webhook_callback_verification") {
+        return match message["challenge"].as_str() {
+            Some(challenge) => Response::new(200)
+                .with_header("Content-Type", "text/plain")
+                .with_body(challenge.as_bytes().to_vec()),
+            None => Response::new(400),
+        };
+    }
+    let event = match parse_event(request.header(MESSAGE_TYPE), &message) {
+        Ok(event) => event,
+        Err(_) => return Response::new(400),
+    };
+    // Twitch resends until it gets a 2xx, duplicates are acknowledged but
+    // dropped; rejected messages aren't remembered so their retry counts
+    if !state.seen.lock().unwrap().insert(id) {
+        return Response::new(204);
+    }
+    if let Some(event) = event {
+        let _ = state.sender.unbounded_send(event);
+    }
+    Response::new(204)
+}
+
+fn verify(secret: &str, id: &str, timestamp: &str, body: &[u8], header: &str) -> bool {
+    let expected = match header
+        .strip_prefix("sha256=")
+        .and_then(|hex_signature| hex::decode(hex_signature).ok())
+    {
+        Some(expected) => expected,
+        None => return false,
+    };
+    // constant time comparison
+    message_mac(secret, id, timestamp, body)
+        .verify_slice(&expected)
+        .is_ok()
+}
+
+/// Typed event of a notification, `None` for subscription types we don't know.
+fn parse_event(message_type: Option<&str>, message: &Value) -> Result<Option<Event>, AsyncError> {
+    let subscription = &message["subscription"];
+    if message_type == Some("revocation") {
+        return Ok(Some(Event::Revoked(serde_json::from_value(
+            subscription.clone(),
+        )?)));
+    }
+    let event = message["event"].clone();
+    let event = match subscription["type"].as_str() {
+        Some("stream.online") => Event::StreamOnline(serde_json::from_value(event)?),
+        Some("stream.offline") => Event::StreamOffline(serde_json::from_value(event)?),
+        Some("channel.update") => Event::ChannelUpdate(serde_json::from_value(event)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_signature() {
+        let body = br#"{"event":{}}"#;
+        let header = signature("0123456789", "id-1", "2023-01-01T00:00:00Z", body);
+
+        assert!(verify(
+            "0123456789",
+            "id-1",
+            "2023-01-01T00:00:00Z",
+            body,
+            &header
+        ));
+        assert!(!verify(
+            "0123456789",
+            "id-2",
+            "2023-01-01T00:00:00Z",
+            body,
+            &header
+        ));
+        assert!(!verify(
+            "9876543210",
+            "id-1",
+            "2023-01-01T00:00:00Z",
+            body,
+            &header
+        ));
+        assert!(!verify(
+            "0123456789",
+            "id-1",
+            "2023-01-01T00:00:00Z",
+            body,
+            "sha256=zz"
+        ));
+    }
+
+    #[test]
+    fn test_seen_messages_evicts_oldest() {
+        let mut seen = SeenMessages::default();
+        for i in 0..=SEEN_MESSAGE_IDS {
+            assert!(seen.insert(&i.to_string()));
+        }
+
+        assert!(!seen.insert(&SEEN_MESSAGE_IDS.to_string()));
+        assert!(seen.insert("0"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Export of Twitch stream schedules as an iCalendar (RFC 5545) file.
+use crate::downloader::{Schedule, ScheduleSegment};
+use chrono::{DateTime, Duration, Utc};
+
+const PRODUCT_ID: &str = "-//cannero//tape_drive//EN";
+const MAX_LINE_LENGTH: usize = 75;
+
+/// One calendar with an event for every upcoming segment of all schedules,
+/// plus one event per vacation.
+pub fn schedules_to_ical(schedules: &[Schedule]) -> String {
+    let stamp = format_time(Utc::now());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Developer streams".to_string(),
+    ];
+    for schedule in schedules {
+        for segment in schedule.upcoming_segments() {
+            lines.extend(segment_event(schedule, segment, &stamp));
+        }
+        if let Some(vacation) = schedule.vacation() {
+            lines.extend(vec![
+                "BEGIN:VEVENT".to_string(),
+                format!("UID:vacation-{}@twitch.tv", schedule.broadcaster_id()),
+                format!("DTSTAMP:{}", stamp),
+                format!("DTSTART:{}", format_time(vacation.start_time)),
+                format!("DTEND:{}", format_time(vacation.end_time)),
+                format!(
+                    "SUMMARY:{}",
+                    escape(&format!("{}: vacation", schedule.broadcaster_name()))
+                ),
+                "TRANSP:TRANSPARENT".to_string(),
+                "END:VEVENT".to_string(),
+            ]);
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+fn segment_event(schedule: &Schedule, segment: &ScheduleSegment, stamp: &str) -> Vec<String> {
+    let channel_url = format!("https://www.twitch.tv/{}", schedule.broadcaster_login());
+    let end_time = segment
+        .end_time()
+        .unwrap_or_else(|| segment.start_time() + Duration::hours(1));
+    let title = if segment.title().is_empty() {
+        "stream"
+    } else {
+        segment.title()
+    };
+    let mut description = vec![];
+    if let Some(category) = segment.category() {
+        description.push(category.name.clone());
+    }
+    if segment.is_recurring() {
+        description.push("recurring".to_string());
+    }
+    description.push(channel_url.clone());
+
+    let mut event = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@twitch.tv", segment.id()),
+        format!("DTSTAMP:{}", stamp),
+        format!("DTSTART:{}", format_time(segment.start_time())),
+        format!("DTEND:{}", format_time(end_time)),
+        format!(
+            "SUMMARY:{}",
+            escape(&format!("{}: {}", schedule.broadcaster_name(), title))
+        ),
+        format!("DESCRIPTION:{}", escape(&description.join("\n"))),
+        format!("URL:{}", channel_url),
+    ];
+    if let Some(category) = segment.category() {
+        event.push(format!("CATEGORIES:{}", escape(&category.name)));
+    }
+    event.push("END:VEVENT".to_string());
+    event
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits lines longer than 75 octets, continuation lines start with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("Rust, C; a\\b\nnext"), "Rust\\, C\\; a\\\\b\\nnext");
+    }
+
+    #[test]
+    fn test_fold() {
+        let line = format!("SUMMARY:{}", "ä".repeat(40));
+
+        let folded = fold(&line);
+
+        assert!(folded.split("\r\n").all(|l| l.len() <= MAX_LINE_LENGTH));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+}
@@ -0,0 +1,265 @@
+//! Content-addressed cache of profile images and offline banners. Every
+//! image is stored once, named by the SHA-256 of its bytes, revalidated
+//! with conditional requests, and `manifest.json` maps user ids to files.
+use crate::cache::Freshness;
+use crate::downloader::{self, Config, TwitchUserData};
+use crate::error::Error;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const OBJECTS_DIR: &str = "objects";
+/// Image downloads in flight.
+const WORKERS: usize = 4;
+
+/// Cached images of a user, paths relative to the cache directory. `None`
+/// if the user has no such image or it was never downloaded.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct UserImages {
+    pub profile_image: Option<PathBuf>,
+    pub offline_image: Option<PathBuf>,
+}
+
+/// File and validators of a downloaded image url.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct CachedImage {
+    file: PathBuf,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Content of `manifest.json`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Manifest {
+    /// By user id.
+    users: BTreeMap<String, UserImages>,
+    /// By url.
+    images: BTreeMap<String, CachedImage>,
+}
+
+impl Manifest {
+    pub fn user(&self, id: &str) -> Option<&UserImages> {
+        self.users.get(id)
+    }
+
+    pub fn users(&self) -> &BTreeMap<String, UserImages> {
+        &self.users
+    }
+}
+
+#[derive(Debug)]
+pub struct ImageSync {
+    pub manifest: Manifest,
+    /// Images that were new or changed.
+    pub downloaded: usize,
+    /// Images the server reported unchanged.
+    pub unchanged: usize,
+    /// Urls that could not be downloaded, their previous file is kept.
+    pub failed: Vec<(String, Error)>,
+}
+
+/// Directory with the images under `objects/` and the manifest.
+#[derive(Clone, Debug)]
+pub struct ImageCache {
+    dir: PathBuf,
+}
+
+impl ImageCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ImageCache { dir: dir.into() }
+    }
+
+    /// Manifest of the last sync, empty if there was none.
+    pub fn manifest(&self) -> Manifest {
+        load_manifest(&self.dir.join(MANIFEST_FILE)).unwrap_or_default()
+    }
+
+    /// Path of a manifest file inside the cache directory.
+    pub fn path(&self, file: &Path) -> PathBuf {
+        self.dir.join(file)
+    }
+
+    /// Downloads the profile images and offline banners of `users` and
+    /// writes the manifest for them. Images that fail are logged and
+    /// reported in `failed`, errors are for the manifest only.
+    #[tracing::instrument(name = "images", skip_all, fields(users = users.len()))]
+    pub async fn sync(
+        &self,
+        config: &Config,
+        users: &[TwitchUserData],
+    ) -> Result<ImageSync, Error> {
+        let previous = self.manifest();
+        let urls: BTreeSet<&String> = users
+            .iter()
+            .flat_map(|u| [u.profile_image_url(), u.offline_image_url()])
+            .filter(|url| !url.is_empty())
+            .collect();
+        let fetches = urls.into_iter().map(|url| {
+            let cached = previous.images.get(url);
+            async move { (url, self.fetch(config, url, cached).await) }
+        });
+        let results: Vec<_> = stream::iter(fetches)
+            .buffer_unordered(WORKERS)
+            .collect()
+            .await;
+
+        let mut sync = ImageSync {
+            manifest: Manifest::default(),
+            downloaded: 0,
+            unchanged: 0,
+            failed: vec![],
+        };
+        let images = &mut sync.manifest.images;
+        for (url, res) in results {
+            let image = match res {
+                Ok((Freshness::Unchanged, image)) => {
+                    sync.unchanged += 1;
+                    Some(image)
+                }
+                Ok((_, image)) => {
+                    sync.downloaded += 1;
+                    Some(image)
+                }
+                Err(e) => {
+                    tracing::warn!(url = url.as_str(), error = %e, "image download failed");
+                    sync.failed.push((url.clone(), e));
+                    previous.images.get(url).cloned()
+                }
+            };
+            if let Some(image) = image {
+                images.insert(url.clone(), image);
+            }
+        }
+        for user in users {
+            let file = |url: &String| images.get(url).map(|image| image.file.clone());
+            let user_images = UserImages {
+                profile_image: file(user.profile_image_url()),
+                offline_image: file(user.offline_image_url()),
+            };
+            sync.manifest.users.insert(user.id().clone(), user_images);
+        }
+        save_manifest(&self.dir, &sync.manifest)?;
+        tracing::info!(
+            downloaded = sync.downloaded,
+            unchanged = sync.unchanged,
+            failed = sync.failed.len(),
+            "images synced"
+        );
+        Ok(sync)
+    }
+
+    async fn fetch(
+        &self,
+        config: &Config,
+        url: &str,
+        cached: Option<&CachedImage>,
+    ) -> Result<(Freshness, CachedImage), Error> {
+        // a deleted file has to be downloaded again
+        let cached = cached.filter(|image| self.path(&image.file).exists());
+        let mut headers = vec![];
+        if let Some(image) = cached {
+            if let Some(etag) = &image.etag {
+                headers.push(("If-None-Match".to_string(), etag.clone()));
+            }
+            if let Some(last_modified) = &image.last_modified {
+                headers.push(("If-Modified-Since".to_string(), last_modified.clone()));
+            }
+        }
+
+        let res = downloader::get_bytes(config, url, headers).await?;
+        match (res.status, cached) {
+            (304, Some(image)) => return Ok((Freshness::Unchanged, image.clone())),
+            (200, _) => {}
+            (status, _) => {
+                return Err(Error::Http {
+                    status,
+                    url: url.to_string(),
+                })
+            }
+        }
+
+        let extension = extension(url, res.header("Content-Type"));
+        let file = Path::new(OBJECTS_DIR).join(format!(
+            "{}.{}",
+            hex::encode(Sha256::digest(&res.body)),
+            extension
+        ));
+        let path = self.path(&file);
+        // same name, same bytes: an image shared by several urls is kept once
+        if !path.exists() {
+            std::fs::create_dir_all(self.dir.join(OBJECTS_DIR))?;
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, &res.body)?;
+            std::fs::rename(tmp, &path)?;
+        }
+        let freshness = match cached {
+            Some(image) if image.file == file => Freshness::Unchanged,
+            _ => Freshness::Changed,
+        };
+        let image = CachedImage {
+            file,
+            etag: res.header("ETag").map(str::to_string),
+            last_modified: res.header("Last-Modified").map(str::to_string),
+        };
+        Ok((freshness, image))
+    }
+}
+
+/// File extension from the content type, else from the url.
+fn extension(url: &str, content_type: Option<&str>) -> String {
+    match content_type
+        .and_then(|t| t.split(';').next())
+        .map(str::trim)
+    {
+        Some("image/png") => return "png".to_string(),
+        Some("image/jpeg") => return "jpg".to_string(),
+        Some("image/gif") => return "gif".to_string(),
+        Some("image/webp") => return "webp".to_string(),
+        _ => {}
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    match path.rsplit_once('.') {
+        Some((_, ext))
+            if !ext.is_empty()
+                && ext.len() <= 4
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            ext.to_ascii_lowercase()
+        }
+        _ => "bin".to_string(),
+    }
+}
+
+/// A missing or unreadable manifest is an empty cache.
+fn load_manifest(path: &Path) -> Option<Manifest> {
+    let file = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&file).ok()
+}
+
+fn save_manifest(dir: &Path, manifest: &Manifest) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(MANIFEST_FILE);
+    let tmp = path.with_extension("json.tmp");
+    let manifest = serde_json::to_string_pretty(manifest).map_err(std::io::Error::from)?;
+    std::fs::write(&tmp, manifest)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension() {
+        let url = "https://static-cdn.jtvnw.net/user-default-pictures/profile_image-300x300.png";
+        assert_eq!(extension(url, Some("image/jpeg; charset=binary")), "jpg");
+        assert_eq!(extension(url, Some("application/octet-stream")), "png");
+        assert_eq!(extension("https://example.com/a.JPEG?v=2", None), "jpeg");
+        assert_eq!(extension("https://example.com/avatar", None), "bin");
+        assert_eq!(extension("https://example.com/a.b/avatar", None), "bin");
+    }
+}
@@ -1,4 +1,22 @@
+pub mod cache;
+pub mod cassette;
+#[cfg(feature = "chat")]
+pub mod chat;
 pub mod downloader;
+pub mod error;
+pub mod eventsub;
+pub mod ical;
+pub mod images;
+pub mod metrics;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod nom_parser;
+pub mod profile;
+mod server;
+pub mod source;
+pub mod transport;
 
+pub use error::Error;
+
+/// Error of the chat client, the EventSub listener and the mock server.
 pub type AsyncError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -1,35 +1,331 @@
-use tape_drive::downloader;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use std::time::Duration;
+use tape_drive::cache::{Freshness, HttpCache};
+use tape_drive::cassette::{Cassette, Recorder};
+use tape_drive::downloader::{self, Strategy};
+use tape_drive::eventsub::{self, Event, WebhookListener};
+use tape_drive::ical;
+use tape_drive::images::ImageCache;
+use tape_drive::metrics::BlockResult;
 use tape_drive::nom_parser;
+use tape_drive::profile;
+use tape_drive::source::ListSource;
 use tape_drive::AsyncError;
+use tracing_subscriber::EnvFilter;
+
+const EVENTSUB_SECRET_KEY: &str = "TWITCH_EVENTSUB_SECRET";
+/// Log filter without `RUST_LOG`.
+const DEFAULT_LOG_FILTER: &str = "tape_drive=info";
 
 #[async_std::main]
 async fn main() -> Result<(), AsyncError> {
-    let file = downloader::download_file(
-        "https://raw.githubusercontent.com/bnb/awesome-developer-streams/master/README.md",
-    )
-    .await?;
+    let args: Vec<String> = std::env::args().collect();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER)),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let config = match arg_value(&args, "--replay") {
+        Some(path) => downloader::Config::new("", "").with_replay(Cassette::load(path)?),
+        None => downloader::Config::from_env()?,
+    };
+    let record_path = arg_value(&args, "--record");
+    let recorder = Recorder::new();
+    let config = match record_path {
+        Some(_) => config.with_recorder(recorder.clone()),
+        None => config,
+    };
+    let config = match arg_value(&args, "--deadline") {
+        Some(seconds) => config.with_crawl_deadline(Duration::from_secs(seconds.parse()?)),
+        None => config,
+    };
+
+    let metrics_server = match arg_value(&args, "--metrics-listen") {
+        Some(addr) => {
+            let server = config.metrics().serve(addr.as_str()).await?;
+            println!("metrics on {}", server.url());
+            Some(server)
+        }
+        None => None,
+    };
 
-    let streamers = nom_parser::parse_file(&file)?;
-    // TODO build hashmap by iterating over streamers, (login, streamer).collect()
-    // for streamer in streamers {
-    //     println!("{:?}", streamer);
-    // }
+    let res = run(&config, &args).await;
+    // save the cassette of failed runs too, they are the interesting ones
+    if let Some(path) = record_path {
+        recorder.save(path)?;
+    }
+    if let Some(path) = arg_value(&args, "--metrics") {
+        config.metrics().write_to(path)?;
+    }
+    drop(metrics_server);
+    res
+}
+
+async fn run(config: &downloader::Config, args: &[String]) -> Result<(), AsyncError> {
+    let cache_dir = arg_value(args, "--cache").map_or(".cache", |d| d.as_str());
+    let source = match arg_value(args, "--list") {
+        Some(source) => source.parse()?,
+        None => ListSource::default(),
+    };
+    let list = source.load(config, &HttpCache::new(cache_dir)).await?;
+    println!("list {} sha256 {}", source, list.sha256());
+    match list.freshness() {
+        Some(Freshness::Changed) => println!("list changed since the last run"),
+        Some(Freshness::Unchanged) => println!("list unchanged since the last run"),
+        Some(Freshness::Offline) => println!("list not reachable, using the cached copy"),
+        None => {}
+    }
+
+    let streamers = match nom_parser::parse_list(list.content()) {
+        Ok(list) => {
+            config
+                .metrics()
+                .record_blocks(BlockResult::Parsed, list.streamers.len());
+            config
+                .metrics()
+                .record_blocks(BlockResult::Skipped, list.skipped.len());
+            list.streamers
+        }
+        Err(e) => {
+            config.metrics().record_parse_failure();
+            return Err(e.into());
+        }
+    };
     println!("{} streamers from file", streamers.len());
 
-    let mut args = std::env::args();
-    if args.any(|a| a == "-s" || a == "--single") {
-        let _twitch_streamers =
-            downloader::get_twitch_users(streamers.iter().map(|s| s.login_name()).collect())
-                .await?;
-    //for tw_s in twitch_streamers {
-    //   println!("{:?}", tw_s);
-    //}
-    } else {
-        let streamers = downloader::get_twitch_users_parallel(
+    if args.iter().any(|a| a == "-l" || a == "--live") {
+        let live_streams = downloader::get_live_streams(
+            config,
             streamers.iter().map(|s| s.login_name()).collect(),
         )
         .await?;
-        println!("{:?}", streamers);
+        println!("{} streamers live", live_streams.len());
+        for stream in live_streams {
+            println!(
+                "{} ({}, {} viewers): {}",
+                stream.user_name(),
+                stream.game_name(),
+                stream.viewer_count(),
+                stream.title()
+            );
+        }
+    } else if args.iter().any(|a| a == "-c" || a == "--clips") {
+        let twitch_streamers = lookup_users(config, &streamers).await?;
+        let ended_at = Utc::now();
+        let clips = downloader::get_top_clips_for_broadcasters(
+            config,
+            twitch_streamers.iter().map(|s| s.id()).collect(),
+            ended_at - chrono::Duration::days(7),
+            ended_at,
+            3,
+        )
+        .await?;
+        for clip in clips.iter().take(10) {
+            println!(
+                "{} views, {}: {} {}",
+                clip.view_count(),
+                clip.broadcaster_name(),
+                clip.title(),
+                clip.url()
+            );
+        }
+    } else if let Some(path) = arg_value(args, "--schedule") {
+        let twitch_streamers = lookup_users(config, &streamers).await?;
+        let schedules =
+            downloader::get_schedules(config, twitch_streamers.iter().map(|s| s.id()).collect())
+                .await?;
+        std::fs::write(path, ical::schedules_to_ical(&schedules))?;
+        println!("{} schedules written to {}", schedules.len(), path);
+    } else if let Some(minutes) = arg_value(args, "--chat") {
+        print_chat_activity(config, &streamers, minutes.parse()?).await?;
+    } else if let Some(callback) = arg_value(args, "--eventsub") {
+        let secret = std::env::var(EVENTSUB_SECRET_KEY).map_err(|e| {
+            format!(
+                "eventsub secret not found, key {}, {}",
+                EVENTSUB_SECRET_KEY, e
+            )
+        })?;
+        let listen = arg_value(args, "--listen").map_or("0.0.0.0:8080", |l| l.as_str());
+        let (_listener, mut events) = WebhookListener::bind(listen, &secret).await?;
+        let twitch_streamers = lookup_users(config, &streamers).await?;
+        let subscriptions = eventsub::create_subscriptions(
+            config,
+            twitch_streamers.iter().map(|s| s.id()).collect(),
+            callback,
+            &secret,
+        )
+        .await?;
+        println!("{} subscriptions created", subscriptions.len());
+        while let Some(event) = events.next().await {
+            match event {
+                Event::StreamOnline(e) => println!("{} is live", e.broadcaster_user_name()),
+                Event::StreamOffline(e) => println!("{} went offline", e.broadcaster_user_name()),
+                Event::ChannelUpdate(e) => println!(
+                    "{} changed title: {} ({})",
+                    e.broadcaster_user_name(),
+                    e.title(),
+                    e.category_name()
+                ),
+                Event::Revoked(s) => println!(
+                    "{} subscription for {} revoked: {}",
+                    s.subscription_type(),
+                    s.broadcaster_user_id(),
+                    s.status()
+                ),
+            }
+        }
+    } else if args.iter().any(|a| a == "-s" || a == "--single") {
+        lookup_users(config, &streamers).await?;
+    } else {
+        let options = fetch_options(args)?.with_followers().with_streams();
+        let lookup = downloader::fetch_users(config, streamers.iter().collect(), &options).await?;
+        report_problems(&lookup);
+        if let Some(dir) = arg_value(args, "--images") {
+            let sync = ImageCache::new(dir).sync(config, &lookup.users).await?;
+            println!(
+                "images: {} downloaded, {} unchanged, {} failed",
+                sync.downloaded,
+                sync.unchanged,
+                sync.failed.len()
+            );
+        }
+        let live_streams = lookup
+            .users
+            .iter()
+            .filter_map(|u| u.live_stream().cloned())
+            .collect();
+        let shared = downloader::shared_followers(&lookup.users, 2);
+        if !shared.is_empty() {
+            println!("{} followers follow several streamers", shared.len());
+        }
+        let profiles = profile::build_profiles(streamers, lookup.users, live_streams);
+        for profile in &profiles.profiles {
+            println!(
+                "{} ({}): {} followers{}, {}",
+                profile.name(),
+                profile.login(),
+                profile.follower_count(),
+                if profile.is_live() { ", live" } else { "" },
+                profile.topics().join(", ")
+            );
+        }
+        for user in &profiles.unmatched_users {
+            eprintln!("twitch user {} not in the README", user.login());
+        }
     }
     Ok(())
 }
+
+/// Twitch users of all streamers, reports the README entries without user
+/// and duplicates.
+async fn lookup_users(
+    config: &downloader::Config,
+    streamers: &[nom_parser::Streamer],
+) -> Result<Vec<downloader::TwitchUserData>, AsyncError> {
+    let options = downloader::FetchOptions::new().with_strategy(Strategy::Sequential);
+    let lookup = downloader::fetch_users(config, streamers.iter().collect(), &options).await?;
+    report_problems(&lookup);
+    Ok(lookup.users)
+}
+
+/// `--workers N` runs N user and follower requests at a time, 1 one after
+/// the other. `--follower-lists` downloads every follower.
+fn fetch_options(args: &[String]) -> Result<downloader::FetchOptions, AsyncError> {
+    let strategy = match arg_value(args, "--workers") {
+        Some(workers) => match workers.parse()? {
+            1 => Strategy::Sequential,
+            workers => Strategy::Concurrent { workers },
+        },
+        None => Strategy::default(),
+    };
+    let options = downloader::FetchOptions::new().with_strategy(strategy);
+    if args.iter().any(|a| a == "--follower-lists") {
+        return Ok(options.with_follower_lists());
+    }
+    Ok(options)
+}
+
+fn report_problems(lookup: &downloader::UserLookup) {
+    for entry in &lookup.missing {
+        eprintln!(
+            "no twitch user for {} ({}), renamed or deleted?",
+            entry.login(),
+            entry.name()
+        );
+    }
+    for diagnostic in &lookup.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if !lookup.unfinished.is_empty() {
+        eprintln!(
+            "{} logins unfinished: {}",
+            lookup.unfinished.len(),
+            lookup.unfinished.join(", ")
+        );
+    }
+}
+
+/// Records the chat of all live streamers for `minutes`.
+#[cfg(feature = "chat")]
+async fn print_chat_activity(
+    config: &downloader::Config,
+    streamers: &[nom_parser::Streamer],
+    minutes: u64,
+) -> Result<(), AsyncError> {
+    let live_streams =
+        downloader::get_live_streams(config, streamers.iter().map(|s| s.login_name()).collect())
+            .await?;
+    println!(
+        "recording chat of {} live streamers for {} minutes",
+        live_streams.len(),
+        minutes
+    );
+    let res = tape_drive::chat::record_activity(
+        &tape_drive::chat::ChatConfig::anonymous(),
+        live_streams.iter().map(|s| s.user_login()).collect(),
+        Duration::from_secs(minutes * 60),
+    )
+    .await;
+    // what was recorded before an error is still worth printing
+    let activity = match &res {
+        Ok(activity) => activity,
+        Err(e) => &e.activity,
+    };
+    for channel in activity {
+        let emotes: Vec<String> = channel
+            .top_emotes()
+            .iter()
+            .take(3)
+            .map(|(emote, count)| format!("{} x{}", emote, count))
+            .collect();
+        println!(
+            "{}: {:.1} messages/min, {} chatters, {}",
+            channel.channel(),
+            channel.message_rate(),
+            channel.unique_chatters(),
+            emotes.join(", ")
+        );
+    }
+    res?;
+    Ok(())
+}
+
+#[cfg(not(feature = "chat"))]
+async fn print_chat_activity(
+    _config: &downloader::Config,
+    _streamers: &[nom_parser::Streamer],
+    _minutes: u64,
+) -> Result<(), AsyncError> {
+    Err("built without the chat feature".into())
+}
+
+/// Value following `name` on the command line, e.g. `--record crawl.json`.
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|pos| args.get(pos + 1))
+}
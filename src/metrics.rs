@@ -0,0 +1,375 @@
+//! Crawl metrics in the Prometheus text format, written to a file at the
+//! end of a run or served on a local `/metrics` endpoint.
+use crate::error::Error;
+use crate::server::{self, Response};
+use crate::AsyncError;
+use async_std::net::{TcpListener, ToSocketAddrs};
+use async_std::task;
+use futures::future::{abortable, AbortHandle};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds in seconds of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Outcome of a README block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockResult {
+    Parsed,
+    /// A streamer known to have no Twitch channel.
+    Skipped,
+}
+
+/// Counters of all requests made with a config, shared between clones.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// By endpoint and status.
+    requests: BTreeMap<(String, String), u64>,
+    latency: BTreeMap<String, Histogram>,
+    rate_limit_waits: u64,
+    rate_limit_wait_seconds: f64,
+    blocks: BTreeMap<&'static str, u64>,
+    readme_parse_failures: u64,
+    logins: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(pos) = LATENCY_BUCKETS.iter().position(|le| value <= *le) {
+            self.buckets[pos] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// `status` is the HTTP status, or `timeout` or `error` for requests
+    /// without response.
+    pub(crate) fn record_request(&self, endpoint: &str, status: &str, latency: Duration) {
+        let endpoint = endpoint.to_string();
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .requests
+            .entry((endpoint.clone(), status.to_string()))
+            .or_default() += 1;
+        registry
+            .latency
+            .entry(endpoint)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    pub(crate) fn record_rate_limit_wait(&self, wait: Duration) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.rate_limit_waits += 1;
+        registry.rate_limit_wait_seconds += wait.as_secs_f64();
+    }
+
+    pub fn record_blocks(&self, result: BlockResult, count: usize) {
+        let label = match result {
+            BlockResult::Parsed => "parsed",
+            BlockResult::Skipped => "skipped",
+        };
+        *self
+            .registry
+            .lock()
+            .unwrap()
+            .blocks
+            .entry(label)
+            .or_default() += count as u64;
+    }
+
+    /// A README that didn't parse, none of its blocks are counted then.
+    pub fn record_parse_failure(&self) {
+        self.registry.lock().unwrap().readme_parse_failures += 1;
+    }
+
+    /// Logins of a user lookup, by whether Twitch returned a user for them
+    /// or the crawl deadline passed first.
+    pub(crate) fn record_logins(&self, matched: usize, unmatched: usize, unfinished: usize) {
+        let mut registry = self.registry.lock().unwrap();
+        for (label, count) in [
+            ("matched", matched),
+            ("unmatched", unmatched),
+            ("unfinished", unfinished),
+        ] {
+            *registry.logins.entry(label).or_default() += count as u64;
+        }
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "requests_total",
+            "counter",
+            "HTTP requests by endpoint and status.",
+        );
+        for ((endpoint, status), count) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "tape_drive_requests_total{{endpoint=\"{}\",status=\"{}\"}} {}",
+                escape(endpoint),
+                escape(status),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "request_duration_seconds",
+            "histogram",
+            "HTTP request latency by endpoint.",
+        );
+        for (endpoint, histogram) in &registry.latency {
+            let endpoint = escape(endpoint);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "tape_drive_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"{}\"}} {}",
+                    endpoint, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "tape_drive_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}",
+                endpoint, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "tape_drive_request_duration_seconds_sum{{endpoint=\"{}\"}} {}",
+                endpoint, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "tape_drive_request_duration_seconds_count{{endpoint=\"{}\"}} {}",
+                endpoint, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "rate_limit_waits_total",
+            "counter",
+            "Waits for the rate limit bucket to refill.",
+        );
+        let _ = writeln!(
+            out,
+            "tape_drive_rate_limit_waits_total {}",
+            registry.rate_limit_waits
+        );
+        header(
+            &mut out,
+            "rate_limit_wait_seconds_total",
+            "counter",
+            "Time spent waiting for the rate limit bucket to refill.",
+        );
+        let _ = writeln!(
+            out,
+            "tape_drive_rate_limit_wait_seconds_total {}",
+            registry.rate_limit_wait_seconds
+        );
+
+        header(
+            &mut out,
+            "readme_blocks_total",
+            "counter",
+            "README streamer blocks by result.",
+        );
+        for (result, count) in &registry.blocks {
+            let _ = writeln!(
+                out,
+                "tape_drive_readme_blocks_total{{result=\"{}\"}} {}",
+                result, count
+            );
+        }
+
+        header(
+            &mut out,
+            "readme_parse_failures_total",
+            "counter",
+            "README downloads that didn't parse.",
+        );
+        let _ = writeln!(
+            out,
+            "tape_drive_readme_parse_failures_total {}",
+            registry.readme_parse_failures
+        );
+
+        header(
+            &mut out,
+            "logins_total",
+            "counter",
+            "Looked up logins by result.",
+        );
+        for (result, count) in &registry.logins {
+            let _ = writeln!(
+                out,
+                "tape_drive_logins_total{{result=\"{}\"}} {}",
+                result, count
+            );
+        }
+        out
+    }
+
+    /// Writes [`render`](Metrics::render) to `path`, e.g. for the node
+    /// exporter textfile collector.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("prom.tmp");
+        std::fs::write(&tmp, self.render())?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Serves the metrics on `GET /metrics` until the server is dropped.
+    pub async fn serve(&self, addr: impl ToSocketAddrs) -> Result<MetricsServer, AsyncError> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let metrics = self.clone();
+        let (serve, abort) = abortable(server::serve(listener, move |request| {
+            let response = match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/metrics") => Response::new(200)
+                    .with_header("Content-Type", "text/plain; version=0.0.4")
+                    .with_body(metrics.render().into_bytes()),
+                _ => Response::new(404),
+            };
+            async move { response }
+        }));
+        task::spawn(serve);
+        Ok(MetricsServer { addr, abort })
+    }
+}
+
+/// Local `/metrics` endpoint. Runs until dropped.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    abort: AbortHandle,
+}
+
+impl MetricsServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/metrics", self.addr)
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP tape_drive_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE tape_drive_{} {}", name, kind);
+}
+
+/// Endpoint label of all image downloads, their paths would each be a
+/// series of their own.
+pub(crate) const IMAGE_ENDPOINT: &str = "image";
+
+/// Path of `url` without scheme, host and query, e.g. `/helix/users`.
+pub(crate) fn endpoint(url: &str) -> String {
+    let url = url.split('?').next().unwrap_or_default();
+    let path = match url.find("://") {
+        Some(pos) => &url[pos + 3..],
+        None => url,
+    };
+    match path.find('/') {
+        Some(pos) => path[pos..].to_string(),
+        None => "/".to_string(),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(
+            endpoint("https://api.twitch.tv/helix/users?login=a&login=b"),
+            "/helix/users"
+        );
+        assert_eq!(endpoint("http://127.0.0.1:8080"), "/");
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_request(
+            &endpoint("https://api.twitch.tv/helix/users?login=a"),
+            "200",
+            Duration::from_millis(80),
+        );
+        metrics.record_request(
+            &endpoint("https://api.twitch.tv/helix/users?login=b"),
+            "429",
+            Duration::from_millis(700),
+        );
+        metrics.record_rate_limit_wait(Duration::from_secs(2));
+        metrics.record_blocks(BlockResult::Parsed, 3);
+        metrics.record_parse_failure();
+        metrics.record_logins(2, 1, 0);
+
+        let text = metrics.render();
+
+        for line in [
+            "tape_drive_requests_total{endpoint=\"/helix/users\",status=\"200\"} 1",
+            "tape_drive_request_duration_seconds_bucket{endpoint=\"/helix/users\",le=\"0.05\"} 0",
+            "tape_drive_request_duration_seconds_bucket{endpoint=\"/helix/users\",le=\"0.1\"} 1",
+            "tape_drive_request_duration_seconds_bucket{endpoint=\"/helix/users\",le=\"1\"} 2",
+            "tape_drive_request_duration_seconds_bucket{endpoint=\"/helix/users\",le=\"+Inf\"} 2",
+            "tape_drive_request_duration_seconds_count{endpoint=\"/helix/users\"} 2",
+            "tape_drive_rate_limit_wait_seconds_total 2",
+            "tape_drive_readme_blocks_total{result=\"parsed\"} 3",
+            "tape_drive_readme_parse_failures_total 1",
+            "tape_drive_logins_total{result=\"unmatched\"} 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "{} missing in\n{}",
+                line,
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,657 @@
+//! In-process mock of the Twitch Helix API, serving fixture data on a local
+//! port so the downloader can be tested without credentials or network.
+use crate::downloader::Config;
+use crate::server::{self, Request, Response};
+use crate::AsyncError;
+use async_std::{net::TcpListener, task};
+use futures::future::{abortable, AbortHandle};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAX_IDS_PER_REQUEST: usize = 100;
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+const RATE_LIMIT: u32 = 800;
+
+/// Helix objects served by the mock, stored in the JSON shape Twitch returns.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    client_id: String,
+    client_secret: String,
+    access_token: String,
+    users: Vec<Value>,
+    follows: Vec<Value>,
+    streams: Vec<Value>,
+    videos: Vec<Value>,
+    clips: Vec<Value>,
+    channels: Vec<Value>,
+    stream_tags: Vec<(String, Value)>,
+    schedules: Vec<Value>,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Fixture {
+            client_id: "mock-client-id".to_string(),
+            client_secret: "mock-client-secret".to_string(),
+            access_token: "mock-access-token".to_string(),
+            users: vec![],
+            follows: vec![],
+            streams: vec![],
+            videos: vec![],
+            clips: vec![],
+            channels: vec![],
+            stream_tags: vec![],
+            schedules: vec![],
+            files: vec![],
+        }
+    }
+}
+
+impl Fixture {
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn client_secret(&self) -> &str {
+        &self.client_secret
+    }
+
+    pub fn access_token(&self) -> &str {
+        &self.access_token
+    }
+
+    /// Adds an entry of `/helix/users`, with all fields of a Helix user.
+    pub fn with_user(mut self, user: Value) -> Self {
+        self.users.push(user);
+        self
+    }
+
+    /// Adds an entry of `/helix/users/follows`, needs `from_id` and `to_id`.
+    pub fn with_follow(mut self, follow: Value) -> Self {
+        self.follows.push(follow);
+        self
+    }
+
+    /// Adds an entry of `/helix/streams`, needs `user_id` and `user_login`.
+    pub fn with_stream(mut self, stream: Value) -> Self {
+        self.streams.push(stream);
+        self
+    }
+
+    /// Adds an entry of `/helix/videos`, needs `user_id` and `type`.
+    pub fn with_video(mut self, video: Value) -> Self {
+        self.videos.push(video);
+        self
+    }
+
+    /// Adds an entry of `/helix/clips`, needs `broadcaster_id`, `created_at`
+    /// and `view_count`.
+    pub fn with_clip(mut self, clip: Value) -> Self {
+        self.clips.push(clip);
+        self
+    }
+
+    /// Adds an entry of `/helix/channels`, needs `broadcaster_id`.
+    pub fn with_channel(mut self, channel: Value) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Adds an entry of `/helix/streams/tags` for the given broadcaster.
+    pub fn with_stream_tag(mut self, broadcaster_id: &str, tag: Value) -> Self {
+        self.stream_tags.push((broadcaster_id.to_string(), tag));
+        self
+    }
+
+    /// Adds the `data` object of `/helix/schedule`, needs `broadcaster_id`
+    /// and `segments`.
+    pub fn with_schedule(mut self, schedule: Value) -> Self {
+        self.schedules.push(schedule);
+        self
+    }
+
+    /// Plain file served without authorization, with ETag and
+    /// Last-Modified, e.g. `/README.md` or `/avatar.png`.
+    pub fn with_file(mut self, path: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.files.push((path.to_string(), body.into()));
+        self
+    }
+}
+
+/// Failure returned instead of the fixture data for one request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// Plain error status, e.g. 500 or 503.
+    Status(u16),
+    /// 429 with `Ratelimit-Remaining: 0`, the bucket resets immediately.
+    RateLimited,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub status: u16,
+}
+
+#[derive(Default)]
+struct State {
+    latency: Duration,
+    page_size: Option<usize>,
+    faults: Vec<(String, VecDeque<Fault>)>,
+    requests: Vec<RecordedRequest>,
+    in_flight: usize,
+    max_in_flight: usize,
+    subscriptions: Vec<Value>,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    fixture: Arc<Fixture>,
+    state: Arc<Mutex<State>>,
+    abort: AbortHandle,
+}
+
+impl MockServer {
+    pub async fn start(fixture: Fixture) -> Result<MockServer, AsyncError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let fixture = Arc::new(fixture);
+        let state = Arc::new(Mutex::new(State {
+            files: fixture.files.clone(),
+            ..State::default()
+        }));
+
+        let handler_fixture = fixture.clone();
+        let handler_state = state.clone();
+        let (serve, abort) = abortable(server::serve(listener, move |request| {
+            handle(request, handler_fixture.clone(), handler_state.clone())
+        }));
+        task::spawn(serve);
+
+        Ok(MockServer {
+            addr,
+            fixture,
+            state,
+            abort,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn helix_url(&self) -> String {
+        format!("{}/helix", self.url())
+    }
+
+    pub fn auth_url(&self) -> String {
+        format!("{}/oauth2", self.url())
+    }
+
+    /// Downloader config pointing at this server, with the fixture credentials.
+    pub fn config(&self) -> Config {
+        Config::new(self.fixture.client_id(), self.fixture.access_token())
+            .with_helix_url(self.helix_url())
+            .with_auth_url(self.auth_url())
+            .with_retry_backoff(Duration::from_millis(1))
+    }
+
+    /// Delay applied to every response.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Caps page sizes below the Helix maximum to force pagination.
+    pub fn set_page_size(&self, page_size: usize) {
+        self.state.lock().unwrap().page_size = Some(page_size);
+    }
+
+    /// Queues a fault for the next request to `path`, e.g. `/helix/users`.
+    /// Faults for the same path are returned in the order they were injected.
+    pub fn inject(&self, path: &str, fault: Fault) {
+        let mut state = self.state.lock().unwrap();
+        match state.faults.iter_mut().find(|(p, _)| p == path) {
+            Some((_, faults)) => faults.push_back(fault),
+            None => state
+                .faults
+                .push((path.to_string(), vec![fault].into_iter().collect())),
+        }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Highest number of requests that were handled at the same time.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.lock().unwrap().max_in_flight
+    }
+
+    /// Replaces the content of a file added with `Fixture::with_file`.
+    pub fn set_file(&self, path: &str, body: impl Into<Vec<u8>>) {
+        let body = body.into();
+        let mut state = self.state.lock().unwrap();
+        match state.files.iter_mut().find(|(p, _)| p == path) {
+            Some((_, file)) => *file = body,
+            None => state.files.push((path.to_string(), body)),
+        }
+    }
+
+    /// EventSub subscriptions created so far.
+    pub fn subscriptions(&self) -> Vec<Value> {
+        self.state.lock().unwrap().subscriptions.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+async fn handle(request: Request, fixture: Arc<Fixture>, state: Arc<Mutex<State>>) -> Response {
+    let (latency, fault) = {
+        let mut state = state.lock().unwrap();
+        state.in_flight += 1;
+        state.max_in_flight = state.max_in_flight.max(state.in_flight);
+        let fault = state
+            .faults
+            .iter_mut()
+            .find(|(p, _)| *p == request.path)
+            .and_then(|(_, faults)| faults.pop_front());
+        (state.latency, fault)
+    };
+    if latency > Duration::from_secs(0) {
+        task::sleep(latency).await;
+    }
+
+    let response = match fault {
+        Some(Fault::Status(status)) => {
+            Response::json(status, &json!({ "error": "injected", "status": status }))
+        }
+        Some(Fault::RateLimited) => {
+            Response::json(429, &json!({ "error": "Too Many Requests", "status": 429 }))
+                .with_header("Ratelimit-Remaining", "0")
+        }
+        None => route(&request, &fixture, &state),
+    };
+    let response = response
+        .with_header("Ratelimit-Limit", RATE_LIMIT.to_string())
+        .with_header("Ratelimit-Reset", unix_now().to_string());
+
+    let mut state = state.lock().unwrap();
+    state.in_flight -= 1;
+    state.requests.push(RecordedRequest {
+        method: request.method,
+        path: request.path,
+        query: request.query,
+        status: response.status,
+    });
+    response
+}
+
+fn route(request: &Request, fixture: &Fixture, state: &Mutex<State>) -> Response {
+    if request.method == "POST" && request.path == "/oauth2/token" {
+        return token(request, fixture);
+    }
+    let file_body = {
+        let state = state.lock().unwrap();
+        state
+            .files
+            .iter()
+            .find(|(path, _)| *path == request.path)
+            .map(|(_, body)| body.clone())
+    };
+    if let Some(body) = file_body {
+        return file(request, &body);
+    }
+    if request.method != "GET" && request.method != "POST" {
+        return error(404, "Not Found");
+    }
+    if let Err(response) = authorize(request, fixture) {
+        return response;
+    }
+    if request.method == "POST" {
+        return match request.path.as_str() {
+            "/helix/eventsub/subscriptions" => create_subscription(request, state),
+            _ => error(404, "Not Found"),
+        };
+    }
+    let page_size = state.lock().unwrap().page_size;
+
+    match request.path.as_str() {
+        "/helix/users" => users(request, fixture),
+        "/helix/users/follows" => follows(request, fixture, page_size),
+        "/helix/streams" => streams(request, fixture, page_size),
+        "/helix/videos" => videos(request, fixture, page_size),
+        "/helix/clips" => clips(request, fixture, page_size),
+        "/helix/channels" => channels(request, fixture),
+        "/helix/streams/tags" => stream_tags(request, fixture, page_size),
+        "/helix/schedule" => schedule(request, fixture, page_size),
+        _ => error(404, "Not Found"),
+    }
+}
+
+fn token(request: &Request, fixture: &Fixture) -> Response {
+    if request.query("grant_type") != Some("client_credentials") {
+        return error(400, "invalid grant type");
+    }
+    if request.query("client_id") != Some(fixture.client_id())
+        || request.query("client_secret") != Some(fixture.client_secret())
+    {
+        return error(403, "invalid client secret");
+    }
+    Response::json(
+        200,
+        &json!({
+            "access_token": fixture.access_token(),
+            "expires_in": 5_000_000,
+            "token_type": "bearer",
+        }),
+    )
+}
+
+fn file(request: &Request, body: &[u8]) -> Response {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+    let last_modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+    let not_modified = match request.header("If-None-Match") {
+        Some(tag) => tag == etag,
+        None => request.header("If-Modified-Since") == Some(last_modified),
+    };
+    let response = if not_modified {
+        Response::new(304)
+    } else {
+        Response::new(200)
+            .with_header("Content-Type", content_type(&request.path))
+            .with_body(body.to_vec())
+    };
+    response
+        .with_header("ETag", etag)
+        .with_header("Last-Modified", last_modified)
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+fn authorize(request: &Request, fixture: &Fixture) -> Result<(), Response> {
+    let bearer = format!("Bearer {}", fixture.access_token());
+    if request.header("Authorization") != Some(bearer.as_str()) {
+        return Err(error(401, "Invalid OAuth token"));
+    }
+    if request.header("Client-ID") != Some(fixture.client_id()) {
+        return Err(error(401, "Client ID and OAuth token do not match"));
+    }
+    Ok(())
+}
+
+fn users(request: &Request, fixture: &Fixture) -> Response {
+    let ids = request.query_all("id");
+    let logins = request.query_all("login");
+    if ids.len() + logins.len() > MAX_IDS_PER_REQUEST {
+        return error(400, "too many ids or logins");
+    }
+    let data: Vec<&Value> = fixture
+        .users
+        .iter()
+        .filter(|user| {
+            ids.iter().any(|id| field(user, "id") == *id)
+                || logins
+                    .iter()
+                    .any(|login| field(user, "login").eq_ignore_ascii_case(login))
+        })
+        .collect();
+    Response::json(200, &json!({ "data": data }))
+}
+
+fn follows(request: &Request, fixture: &Fixture, page_size: Option<usize>) -> Response {
+    let from_id = request.query("from_id");
+    let to_id = request.query("to_id");
+    if from_id.is_none() && to_id.is_none() {
+        return error(400, "from_id or to_id is required");
+    }
+    let matching: Vec<&Value> = fixture
+        .follows
+        .iter()
+        .filter(|follow| from_id.is_none_or(|id| field(follow, "from_id") == id))
+        .filter(|follow| to_id.is_none_or(|id| field(follow, "to_id") == id))
+        .collect();
+    let total = matching.len();
+    match paginate(request, matching, page_size) {
+        Ok((data, pagination)) => Response::json(
+            200,
+            &json!({ "total": total, "data": data, "pagination": pagination }),
+        ),
+        Err(response) => response,
+    }
+}
+
+fn streams(request: &Request, fixture: &Fixture, page_size: Option<usize>) -> Response {
+    let ids = request.query_all("user_id");
+    let logins = request.query_all("user_login");
+    if ids.len() > MAX_IDS_PER_REQUEST || logins.len() > MAX_IDS_PER_REQUEST {
+        return error(400, "too many user ids or logins");
+    }
+    let matching: Vec<&Value> = fixture
+        .streams
+        .iter()
+        .filter(|stream| {
+            (ids.is_empty() && logins.is_empty())
+                || ids.iter().any(|id| field(stream, "user_id") == *id)
+                || logins
+                    .iter()
+                    .any(|login| field(stream, "user_login").eq_ignore_ascii_case(login))
+        })
+        .collect();
+    match paginate(request, matching, page_size) {
+        Ok((data, pagination)) => {
+            Response::json(200, &json!({ "data": data, "pagination": pagination }))
+        }
+        Err(response) => response,
+    }
+}
+
+fn videos(request: &Request, fixture: &Fixture, page_size: Option<usize>) -> Response {
+    let user_id = match request.query("user_id") {
+        Some(user_id) => user_id,
+        None => return error(400, "user_id is required"),
+    };
+    let video_type = request.query("type").unwrap_or("all");
+    let matching: Vec<&Value> = fixture
+        .videos
+        .iter()
+        .filter(|video| field(video, "user_id") == user_id)
+        .filter(|video| video_type == "all" || field(video, "type") == video_type)
+        .collect();
+    match paginate(request, matching, page_size) {
+        Ok((data, pagination)) => {
+            Response::json(200, &json!({ "data": data, "pagination": pagination }))
+        }
+        Err(response) => response,
+    }
+}
+
+fn clips(request: &Request, fixture: &Fixture, page_size: Option<usize>) -> Response {
+    let broadcaster_id = match request.query("broadcaster_id") {
+        Some(broadcaster_id) => broadcaster_id,
+        None => return error(400, "broadcaster_id is required"),
+    };
+    // timestamps are all RFC 3339 in UTC, they compare as strings
+    let started_at = request.query("started_at").unwrap_or("");
+    let ended_at = request.query("ended_at");
+    let mut matching: Vec<&Value> = fixture
+        .clips
+        .iter()
+        .filter(|clip| field(clip, "broadcaster_id") == broadcaster_id)
+        .filter(|clip| field(clip, "created_at") >= started_at)
+        .filter(|clip| ended_at.is_none_or(|ended_at| field(clip, "created_at") <= ended_at))
+        .collect();
+    matching.sort_by_key(|clip| std::cmp::Reverse(clip["view_count"].as_u64()));
+    match paginate(request, matching, page_size) {
+        Ok((data, pagination)) => {
+            Response::json(200, &json!({ "data": data, "pagination": pagination }))
+        }
+        Err(response) => response,
+    }
+}
+
+fn channels(request: &Request, fixture: &Fixture) -> Response {
+    let ids = request.query_all("broadcaster_id");
+    if ids.is_empty() || ids.len() > MAX_IDS_PER_REQUEST {
+        return error(400, "between 1 and 100 broadcaster_id are required");
+    }
+    let data: Vec<&Value> = fixture
+        .channels
+        .iter()
+        .filter(|channel| ids.contains(&field(channel, "broadcaster_id")))
+        .collect();
+    Response::json(200, &json!({ "data": data }))
+}
+
+fn stream_tags(request: &Request, fixture: &Fixture, page_size: Option<usize>) -> Response {
+    let broadcaster_id = match request.query("broadcaster_id") {
+        Some(broadcaster_id) => broadcaster_id,
+        None => return error(400, "broadcaster_id is required"),
+    };
+    let matching: Vec<&Value> = fixture
+        .stream_tags
+        .iter()
+        .filter(|(id, _)| id == broadcaster_id)
+        .map(|(_, tag)| tag)
+        .collect();
+    match paginate(request, matching, page_size) {
+        Ok((data, pagination)) => {
+            Response::json(200, &json!({ "data": data, "pagination": pagination }))
+        }
+        Err(response) => response,
+    }
+}
+
+fn schedule(request: &Request, fixture: &Fixture, page_size: Option<usize>) -> Response {
+    let broadcaster_id = match request.query("broadcaster_id") {
+        Some(broadcaster_id) => broadcaster_id,
+        None => return error(400, "broadcaster_id is required"),
+    };
+    let schedule = match fixture
+        .schedules
+        .iter()
+        .find(|schedule| field(schedule, "broadcaster_id") == broadcaster_id)
+    {
+        Some(schedule) => schedule,
+        None => return error(404, "schedule not found"),
+    };
+    let segments: Vec<&Value> = schedule["segments"]
+        .as_array()
+        .map(|segments| segments.iter().collect())
+        .unwrap_or_default();
+    match paginate(request, segments, page_size) {
+        Ok((segments, pagination)) => {
+            let mut data = schedule.clone();
+            data["segments"] = json!(segments);
+            Response::json(200, &json!({ "data": data, "pagination": pagination }))
+        }
+        Err(response) => response,
+    }
+}
+
+/// Stores a webhook subscription, 409 if the same one exists.
+fn create_subscription(request: &Request, state: &Mutex<State>) -> Response {
+    let body: Value = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(_) => return error(400, "invalid body"),
+    };
+    if body["transport"]["method"] != "webhook" || body["transport"]["secret"].is_null() {
+        return error(400, "invalid transport");
+    }
+
+    let mut state = state.lock().unwrap();
+    let exists = state
+        .subscriptions
+        .iter()
+        .any(|s| s["type"] == body["type"] && s["condition"] == body["condition"]);
+    if exists {
+        return error(409, "subscription already exists");
+    }
+    let subscription = json!({
+        "id": format!("sub-{}", state.subscriptions.len() + 1),
+        "status": "webhook_callback_verification_pending",
+        "type": body["type"],
+        "version": body["version"],
+        "condition": body["condition"],
+        "transport": {
+            "method": "webhook",
+            "callback": body["transport"]["callback"],
+        },
+        "cost": 1,
+    });
+    state.subscriptions.push(subscription.clone());
+    Response::json(
+        202,
+        &json!({ "data": [subscription], "total": state.subscriptions.len() }),
+    )
+}
+
+/// Cuts one page out of `items`, the cursor is the offset of the next page.
+fn paginate<'a>(
+    request: &Request,
+    items: Vec<&'a Value>,
+    page_size: Option<usize>,
+) -> Result<(Vec<&'a Value>, Value), Response> {
+    let first = match request.query("first") {
+        Some(first) => match first.parse::<usize>() {
+            Ok(first) if (1..=MAX_PAGE_SIZE).contains(&first) => first,
+            _ => return Err(error(400, "invalid value for first")),
+        },
+        None => DEFAULT_PAGE_SIZE,
+    };
+    let first = page_size.map_or(first, |size| first.min(size));
+    let start = match request.query("after") {
+        Some(after) => match after.parse::<usize>() {
+            Ok(start) => start,
+            Err(_) => return Err(error(400, "invalid cursor")),
+        },
+        None => 0,
+    };
+    let end = items.len().min(start + first);
+    let page = items.get(start..end).unwrap_or(&[]).to_vec();
+    let pagination = if end < items.len() {
+        json!({ "cursor": end.to_string() })
+    } else {
+        json!({})
+    };
+    Ok((page, pagination))
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> &'a str {
+    value.get(name).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn error(status: u16, message: &str) -> Response {
+    Response::json(
+        status,
+        &json!({ "error": server::reason(status), "status": status, "message": message }),
+    )
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until, take_while},
@@ -9,7 +10,7 @@ use nom::{
 
 const CHANNEL_START_ID: &str = "- [Twitch](";
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Streamer {
     name: String,
     login: String,
@@ -17,6 +18,18 @@ pub struct Streamer {
 }
 
 impl Streamer {
+    pub fn new(
+        name: impl Into<String>,
+        login: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Self {
+        Streamer {
+            name: name.into(),
+            login: login.into(),
+            content: content.into(),
+        }
+    }
+
     pub fn login_name(&self) -> &String {
         &self.login
     }
@@ -24,17 +37,77 @@ impl Streamer {
     pub fn name(&self) -> &String {
         &self.name
     }
+
+    /// Topics from the "What ... streams" list.
+    pub fn topics(&self) -> Vec<&str> {
+        self.content
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .collect()
+    }
 }
 
-pub fn parse_file(input: &str) -> Result<Vec<Streamer>, String> {
-    let (input, _) = streamers_block_start(input).unwrap();
-    match parse_streamers(input) {
-        Ok((_, (streamers, _))) => Ok(streamers),
-        Err(e) => Err(format! {"Failed {}", e}),
+/// Streamers of the list together with the blocks that were left out.
+#[derive(Clone, Debug)]
+pub struct ParsedList {
+    pub streamers: Vec<Streamer>,
+    /// Names of streamers known to have no Twitch channel.
+    pub skipped: Vec<String>,
+}
+
+pub fn parse_file(input: &str) -> Result<Vec<Streamer>, Error> {
+    parse_list(input).map(|list| list.streamers)
+}
+
+#[tracing::instrument(name = "parse", skip_all, fields(bytes = input.len()))]
+pub fn parse_list(input: &str) -> Result<ParsedList, Error> {
+    let (streamers, _) = streamers_block_start(input).map_err(|_| Error::Parse {
+        message: "no \"# Developers That Stream\" section".to_string(),
+        offset: None,
+        source: None,
+    })?;
+    match parse_streamers(streamers) {
+        Ok((_, (blocks, _))) => {
+            let mut list = ParsedList {
+                streamers: vec![],
+                skipped: vec![],
+            };
+            for block in blocks {
+                match block {
+                    Block::Streamer(streamer) => list.streamers.push(streamer),
+                    Block::Skipped(name) => list.skipped.push(name),
+                }
+            }
+            tracing::info!(
+                streamers = list.streamers.len(),
+                skipped = list.skipped.len(),
+                "streamer list parsed"
+            );
+            Ok(list)
+        }
+        Err(e) => {
+            // the rest of the file is no use in the error, its offset is
+            let e = e.map_input(|rest: &str| input.len() - rest.len());
+            Err(Error::Parse {
+                message: "streamer block not recognized".to_string(),
+                offset: match &e {
+                    nom::Err::Error((offset, _)) | nom::Err::Failure((offset, _)) => Some(*offset),
+                    nom::Err::Incomplete(_) => None,
+                },
+                source: Some(Box::new(e)),
+            })
+        }
     }
 }
 
-fn parse_streamers(input: &str) -> IResult<&str, (Vec<Streamer>, &str)> {
+#[derive(Debug, PartialEq)]
+enum Block {
+    Streamer(Streamer),
+    Skipped(String),
+}
+
+fn parse_streamers(input: &str) -> IResult<&str, (Vec<Block>, &str)> {
     many_till(parse_streamer, tag("\n## Twitch"))(input)
 }
 
@@ -43,7 +116,7 @@ fn streamers_block_start(input: &str) -> IResult<&str, &str> {
     tag("# Developers That Stream\n\n")(input)
 }
 
-fn parse_streamer(input: &str) -> IResult<&str, Streamer> {
+fn parse_streamer(input: &str) -> IResult<&str, Block> {
     let (input, name) = streamer_name(input)?;
 
     if vec![
@@ -67,7 +140,7 @@ fn parse_streamer(input: &str) -> IResult<&str, Streamer> {
     {
         //take next streamer, no twitch account
         let (input, _) = streamer_start(input)?;
-        return parse_streamer(input);
+        return Ok((input, Block::Skipped(name.trim().to_string())));
     }
 
     let (input, _) = content_start(input)?;
@@ -83,11 +156,11 @@ fn parse_streamer(input: &str) -> IResult<&str, Streamer> {
     let (input, _) = streamer_start(input)?;
     Ok((
         input,
-        Streamer {
+        Block::Streamer(Streamer {
             name: name.trim().to_string(),
             login: login.trim_matches('/').to_string(),
             content: content.trim_matches('-').trim().to_string(),
-        },
+        }),
     ))
 }
 
@@ -120,7 +193,7 @@ fn streamer_start(input: &str) -> IResult<&str, &str> {
 fn channel_start<'a>(input: &'a str, channel_start_id: &str) -> IResult<&'a str, &'a str> {
     let (input, taken) = take_until(channel_start_id)(input)?;
     if taken.contains("---") {
-        eprintln!("--- found");
+        tracing::debug!("\"---\" found before the channel link");
         Err(nom::Err::Error((taken, nom::error::ErrorKind::OneOf)))
     } else {
         Ok((input, taken))
@@ -131,7 +204,7 @@ fn content_start(input: &str) -> IResult<&str, &str> {
     let content_headline_start = "#### What ";
     let (input, taken) = take_until(content_headline_start)(input)?;
     if taken.contains("--") {
-        eprintln!("-- found during content start search");
+        tracing::debug!("\"--\" found before the content headline");
         return Err(nom::Err::Error((taken, nom::error::ErrorKind::OneOf)));
     }
 
@@ -341,11 +414,11 @@ Angular 6+, SCSS, LUA, Node.js, Python, SQL, Typescript, WASM, Web Development
 
         assert_eq!(
             streamer,
-            Streamer {
+            Block::Streamer(Streamer {
                 name: "Mike Conley".to_string(),
                 login: "mikeconley_dot_ca".to_string(),
                 content: "Firefox Development, JavaScript, C++, CSS, Rust".to_string(),
-            }
+            })
         );
     }
 
@@ -401,6 +474,20 @@ Angular 6+, SCSS, LUA, Node.js, Python, SQL, Typescript, WASM, Web Development
         assert_eq!(streamers.len(), 3);
     }
 
+    #[test]
+    fn test_streamer_topics() {
+        let streamer = Streamer {
+            name: "Mike Conley".to_string(),
+            login: "mikeconley_dot_ca".to_string(),
+            content: "Firefox Development, JavaScript, C++, CSS, Rust".to_string(),
+        };
+
+        assert_eq!(
+            streamer.topics(),
+            vec!["Firefox Development", "JavaScript", "C++", "CSS", "Rust"]
+        );
+    }
+
     #[test]
     fn test_content_start() {
         let input = "#### What Brookzerker streams:
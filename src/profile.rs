@@ -0,0 +1,175 @@
+//! Joins the README streamers with their Twitch data.
+use crate::downloader::{LiveStream, TwitchUserData};
+use crate::nom_parser::Streamer;
+use std::collections::HashMap;
+
+/// README entry together with its Twitch user and, while live, its stream.
+#[derive(Clone, Debug)]
+pub struct StreamerProfile {
+    streamer: Streamer,
+    user: TwitchUserData,
+    live_stream: Option<LiveStream>,
+}
+
+impl StreamerProfile {
+    pub fn streamer(&self) -> &Streamer {
+        &self.streamer
+    }
+
+    pub fn user(&self) -> &TwitchUserData {
+        &self.user
+    }
+
+    pub fn live_stream(&self) -> Option<&LiveStream> {
+        self.live_stream.as_ref()
+    }
+
+    /// README name.
+    pub fn name(&self) -> &String {
+        self.streamer.name()
+    }
+
+    /// Twitch login, lowercase.
+    pub fn login(&self) -> &String {
+        self.user.login()
+    }
+
+    pub fn topics(&self) -> Vec<&str> {
+        self.streamer.topics()
+    }
+
+    pub fn follower_count(&self) -> u32 {
+        self.user.follower_count()
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live_stream.is_some()
+    }
+}
+
+/// Result of [`build_profiles`], with the entries that found no partner.
+#[derive(Debug)]
+pub struct Profiles {
+    /// In README order.
+    pub profiles: Vec<StreamerProfile>,
+    /// README entries without Twitch user, or listed a second time.
+    pub unmatched_streamers: Vec<Streamer>,
+    /// Twitch users no README entry asked for.
+    pub unmatched_users: Vec<TwitchUserData>,
+}
+
+impl Profiles {
+    /// Profile of `login`, case-insensitive.
+    pub fn find(&self, login: &str) -> Option<&StreamerProfile> {
+        self.profiles
+            .iter()
+            .find(|p| p.login().eq_ignore_ascii_case(login))
+    }
+}
+
+/// Matches streamers and users by case-insensitive login and live streams
+/// by user id. Streams of users without profile are dropped.
+pub fn build_profiles(
+    streamers: Vec<Streamer>,
+    users: Vec<TwitchUserData>,
+    live_streams: Vec<LiveStream>,
+) -> Profiles {
+    let mut users: HashMap<String, TwitchUserData> = users
+        .into_iter()
+        .map(|u| (u.login().to_lowercase(), u))
+        .collect();
+    let mut live_streams: HashMap<String, LiveStream> = live_streams
+        .into_iter()
+        .map(|s| (s.user_id().clone(), s))
+        .collect();
+
+    let mut profiles = vec![];
+    let mut unmatched_streamers = vec![];
+    for streamer in streamers {
+        match users.remove(&streamer.login_name().to_lowercase()) {
+            Some(user) => {
+                let live_stream = live_streams.remove(user.id());
+                profiles.push(StreamerProfile {
+                    streamer,
+                    user,
+                    live_stream,
+                });
+            }
+            None => unmatched_streamers.push(streamer),
+        }
+    }
+    let mut unmatched_users: Vec<TwitchUserData> = users.into_values().collect();
+    unmatched_users.sort_by(|a, b| a.login().cmp(b.login()));
+
+    Profiles {
+        profiles,
+        unmatched_streamers,
+        unmatched_users,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn user(id: &str, login: &str) -> TwitchUserData {
+        serde_json::from_value(json!({
+            "id": id,
+            "login": login,
+            "display_name": login,
+            "type": "",
+            "broadcaster_type": "",
+            "description": "",
+            "profile_image_url": "",
+            "offline_image_url": "",
+            "view_count": 1,
+            "created_at": "2016-12-14T20:32:28Z",
+        }))
+        .unwrap()
+    }
+
+    fn live_stream(user_id: &str) -> LiveStream {
+        serde_json::from_value(json!({
+            "user_id": user_id,
+            "user_login": "",
+            "user_name": "",
+            "game_id": "509670",
+            "game_name": "Science & Technology",
+            "title": "live coding",
+            "viewer_count": 5,
+            "started_at": "2020-05-21T15:42:00Z",
+            "language": "en",
+            "thumbnail_url": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_profiles() {
+        let streamers = vec![
+            Streamer::new("Ann", "Ann_Codes", "rust, wasm"),
+            Streamer::new("Gone", "deleted_user", "go"),
+            Streamer::new("Bob", "bob", "c++"),
+            Streamer::new("Bob again", "BOB", "c++"),
+        ];
+        let users = vec![user("1", "ann_codes"), user("2", "bob"), user("3", "carl")];
+
+        let profiles = build_profiles(streamers, users, vec![live_stream("2"), live_stream("9")]);
+
+        assert_eq!(profiles.profiles.len(), 2);
+        assert_eq!(profiles.profiles[0].name(), "Ann");
+        assert_eq!(profiles.profiles[0].topics(), vec!["rust", "wasm"]);
+        assert!(!profiles.profiles[0].is_live());
+        assert_eq!(profiles.find("BOB").unwrap().user().id(), "2");
+        assert!(profiles.find("bob").unwrap().is_live());
+        let unmatched: Vec<&String> = profiles
+            .unmatched_streamers
+            .iter()
+            .map(|s| s.name())
+            .collect();
+        assert_eq!(unmatched, vec!["Gone", "Bob again"]);
+        assert_eq!(profiles.unmatched_users.len(), 1);
+        assert_eq!(profiles.unmatched_users[0].login(), "carl");
+    }
+}
@@ -0,0 +1,204 @@
+//! Where the awesome list is read from: a local file, stdin, any url or a
+//! pinned revision of a GitHub repository.
+use crate::cache::{Freshness, HttpCache};
+use crate::downloader::Config;
+use crate::error::Error;
+use async_std::io::ReadExt;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const DEFAULT_OWNER: &str = "bnb";
+const DEFAULT_REPO: &str = "awesome-developer-streams";
+const DEFAULT_REF: &str = "master";
+const LIST_FILE: &str = "README.md";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListSource {
+    Path(PathBuf),
+    Stdin,
+    Url(String),
+    /// README of `owner/repo` at `reference`, a branch, tag or commit SHA.
+    GitHub {
+        owner: String,
+        repo: String,
+        reference: String,
+    },
+}
+
+impl Default for ListSource {
+    fn default() -> Self {
+        ListSource::GitHub {
+            owner: DEFAULT_OWNER.to_string(),
+            repo: DEFAULT_REPO.to_string(),
+            reference: DEFAULT_REF.to_string(),
+        }
+    }
+}
+
+/// Parses the `--list` argument: `-` for stdin, an `http(s)://` url,
+/// `github:owner/repo@ref` (`@ref` defaults to master) or a file path.
+impl FromStr for ListSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(ListSource::Stdin);
+        }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(ListSource::Url(s.to_string()));
+        }
+        if let Some(repo) = s.strip_prefix("github:") {
+            let (repo, reference) = match repo.find('@') {
+                Some(pos) => (&repo[..pos], &repo[pos + 1..]),
+                None => (repo, DEFAULT_REF),
+            };
+            let mut parts = repo.split('/');
+            return match (parts.next(), parts.next(), parts.next()) {
+                (Some(owner), Some(repo), None)
+                    if !owner.is_empty() && !repo.is_empty() && !reference.is_empty() =>
+                {
+                    Ok(ListSource::GitHub {
+                        owner: owner.to_string(),
+                        repo: repo.to_string(),
+                        reference: reference.to_string(),
+                    })
+                }
+                _ => Err(Error::config(format!(
+                    "expected github:owner/repo@ref, got {}",
+                    s
+                ))),
+            };
+        }
+        Ok(ListSource::Path(PathBuf::from(s)))
+    }
+}
+
+impl fmt::Display for ListSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListSource::Path(path) => write!(f, "{}", path.display()),
+            ListSource::Stdin => write!(f, "stdin"),
+            ListSource::Url(url) => write!(f, "{}", url),
+            ListSource::GitHub {
+                owner,
+                repo,
+                reference,
+            } => write!(f, "github:{}/{}@{}", owner, repo, reference),
+        }
+    }
+}
+
+impl ListSource {
+    /// Url the list is downloaded from, `None` for local sources.
+    pub fn url(&self) -> Option<String> {
+        match self {
+            ListSource::Url(url) => Some(url.clone()),
+            ListSource::GitHub {
+                owner,
+                repo,
+                reference,
+            } => Some(format!(
+                "https://raw.githubusercontent.com/{}/{}/{}/{}",
+                owner, repo, reference, LIST_FILE
+            )),
+            ListSource::Path(_) | ListSource::Stdin => None,
+        }
+    }
+
+    /// Reads the list, downloads go through `cache`.
+    #[tracing::instrument(name = "list", skip_all, fields(source = %self))]
+    pub async fn load(&self, config: &Config, cache: &HttpCache) -> Result<List, Error> {
+        let (content, freshness) = match self {
+            ListSource::Path(path) => (async_std::fs::read_to_string(path).await?, None),
+            ListSource::Stdin => {
+                let mut content = String::new();
+                async_std::io::stdin().read_to_string(&mut content).await?;
+                (content, None)
+            }
+            ListSource::Url(_) | ListSource::GitHub { .. } => {
+                let url = self.url().unwrap();
+                let download = cache.download(config, &url).await?;
+                let freshness = download.freshness();
+                (download.into_body(), Some(freshness))
+            }
+        };
+        let sha256 = sha256_hex(&content);
+        tracing::info!(bytes = content.len(), %sha256, ?freshness, "list loaded");
+        Ok(List {
+            sha256,
+            content,
+            freshness,
+        })
+    }
+}
+
+/// Content of the list as it was parsed.
+#[derive(Clone, Debug)]
+pub struct List {
+    content: String,
+    sha256: String,
+    freshness: Option<Freshness>,
+}
+
+impl List {
+    pub fn content(&self) -> &String {
+        &self.content
+    }
+
+    /// Hex SHA-256 of the content, identifies the exact list a run used.
+    pub fn sha256(&self) -> &String {
+        &self.sha256
+    }
+
+    /// Compared to the cached copy, `None` for local sources.
+    pub fn freshness(&self) -> Option<Freshness> {
+        self.freshness
+    }
+}
+
+fn sha256_hex(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sources() {
+        assert_eq!("-".parse::<ListSource>().unwrap(), ListSource::Stdin);
+        assert_eq!(
+            "files/README.md".parse::<ListSource>().unwrap(),
+            ListSource::Path(PathBuf::from("files/README.md"))
+        );
+        assert_eq!(
+            "https://example.com/list.md".parse::<ListSource>().unwrap(),
+            ListSource::Url("https://example.com/list.md".to_string())
+        );
+        let pinned: ListSource = "github:bnb/awesome-developer-streams@3f2a9c1"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            pinned.url().unwrap(),
+            "https://raw.githubusercontent.com/bnb/awesome-developer-streams/3f2a9c1/README.md"
+        );
+        assert_eq!(
+            "github:bnb/awesome-developer-streams"
+                .parse::<ListSource>()
+                .unwrap(),
+            ListSource::default()
+        );
+        assert!("github:bnb".parse::<ListSource>().is_err());
+        assert!("github:bnb/repo@".parse::<ListSource>().is_err());
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
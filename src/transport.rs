@@ -0,0 +1,283 @@
+//! The HTTP client behind all downloader requests. [`SurfTransport`] is
+//! used by default, [`MemoryTransport`] answers from canned responses for
+//! tests, and any other client can be plugged in with
+//! [`Config::with_transport`](crate::downloader::Config::with_transport).
+use crate::error::Error;
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Response headers the surf transport passes on.
+const SURF_RESPONSE_HEADERS: [&str; 7] = [
+    "Content-Type",
+    "ETag",
+    "Last-Modified",
+    "Location",
+    "Ratelimit-Limit",
+    "Ratelimit-Remaining",
+    "Ratelimit-Reset",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// JSON body, sent with `Content-Type: application/json`.
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        HttpRequest {
+            method: "GET".to_string(),
+            url: url.into(),
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    pub fn post_json(url: impl Into<String>, body: &serde_json::Value) -> Self {
+        HttpRequest {
+            method: "POST".to_string(),
+            url: url.into(),
+            headers: vec![],
+            body: Some(body.to_string()),
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// Raw body, images are passed on as is.
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        HttpResponse {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// First value of a header, names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Body as text, invalid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Sends a request and returns the whole response. Any status is a
+/// response, errors are reserved for requests that got no answer.
+/// Timeouts, retries and recording are handled by the downloader.
+pub trait HttpTransport: fmt::Debug + Send + Sync {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>>;
+
+    fn get(
+        &self,
+        url: &str,
+        headers: Vec<(String, String)>,
+    ) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        let mut request = HttpRequest::get(url);
+        request.headers = headers;
+        self.send(request)
+    }
+}
+
+/// Transport on top of surf's default client.
+#[derive(Clone, Debug, Default)]
+pub struct SurfTransport;
+
+impl SurfTransport {
+    pub fn new() -> Self {
+        SurfTransport
+    }
+}
+
+impl HttpTransport for SurfTransport {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        Box::pin(async move {
+            let url = request.url.clone();
+            let error = |e| Error::transport(&url, e);
+            let mut req = match request.method.as_str() {
+                "GET" => surf::get(&request.url),
+                "HEAD" => surf::head(&request.url),
+                "POST" => surf::post(&request.url),
+                "PUT" => surf::put(&request.url),
+                "DELETE" => surf::delete(&request.url),
+                "PATCH" => surf::patch(&request.url),
+                method => {
+                    let message = format!("unsupported method {}", method);
+                    return Err(Error::transport(&url, message));
+                }
+            };
+            if let Some(body) = request.body {
+                req = req.body_string(body);
+                req = req.set_header("Content-Type".parse().unwrap(), "application/json");
+            }
+            for (name, value) in &request.headers {
+                req = req.set_header(name.parse().map_err(error)?, value);
+            }
+            let mut res = req.await.map_err(error)?;
+            // surf can't list the headers of a response, only look them up
+            let headers = SURF_RESPONSE_HEADERS
+                .iter()
+                .filter_map(|name| {
+                    res.header(&name.parse().unwrap())
+                        .and_then(|values| values.first())
+                        .map(|value| (name.to_string(), value.as_str().to_string()))
+                })
+                .collect();
+            Ok(HttpResponse {
+                status: res.status().into(),
+                headers,
+                body: res
+                    .body_bytes()
+                    .await
+                    .map_err(|e| Error::transport(&url, e))?,
+            })
+        })
+    }
+}
+
+/// Answers requests from canned responses and keeps every request it got.
+/// Responses are matched by method and url, the query included; several
+/// responses for the same request are served in order, the last repeats.
+/// Clones share their state.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    responses: HashMap<(String, String), Vec<HttpResponse>>,
+    requests: Vec<HttpRequest>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        MemoryTransport::default()
+    }
+
+    pub fn with_response(self, method: &str, url: &str, response: HttpResponse) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .entry((method.to_string(), url.to_string()))
+            .or_default()
+            .push(response);
+        self
+    }
+
+    /// Requests received so far, in order.
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl HttpTransport for MemoryTransport {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        let mut state = self.state.lock().unwrap();
+        let key = (request.method.clone(), request.url.clone());
+        let response = match state.responses.get_mut(&key) {
+            Some(responses) if responses.len() > 1 => Ok(responses.remove(0)),
+            Some(responses) => Ok(responses[0].clone()),
+            None => Err(Error::transport(
+                &request.url,
+                format!("no response for {}", request.method),
+            )),
+        };
+        state.requests.push(request);
+        Box::pin(async move { response })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Fixture, MockServer};
+
+    #[async_std::test]
+    async fn test_surf_transport_methods() {
+        let server = MockServer::start(Fixture::default()).await.unwrap();
+        let transport = SurfTransport::new();
+        for method in ["PUT", "DELETE", "PATCH"] {
+            let mut request = HttpRequest::get(format!("{}/helix/users", server.url()));
+            request.method = method.to_string();
+            transport.send(request).await.unwrap();
+        }
+        let mut request = HttpRequest::get(server.url());
+        request.method = "TRACE".to_string();
+
+        assert!(transport.send(request).await.is_err());
+        let methods: Vec<String> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, ["PUT", "DELETE", "PATCH"]);
+    }
+
+    #[async_std::test]
+    async fn test_memory_transport() {
+        let url = "https://example.com/users?login=ann";
+        let transport = MemoryTransport::new()
+            .with_response("GET", url, HttpResponse::new(429, ""))
+            .with_response(
+                "GET",
+                url,
+                HttpResponse::new(200, r#"{"id":"1"}"#).with_header("ETag", "\"v1\""),
+            );
+
+        assert_eq!(transport.get(url, vec![]).await.unwrap().status, 429);
+        for _ in 0..2 {
+            let res = transport
+                .get(
+                    url,
+                    vec![("If-None-Match".to_string(), "\"v0\"".to_string())],
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.header("etag"), Some("\"v1\""));
+            let body: serde_json::Value = res.json().unwrap();
+            assert_eq!(body["id"], "1");
+        }
+        assert!(transport.get("https://example.com/", vec![]).await.is_err());
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[1].header("if-none-match"), Some("\"v0\""));
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+use tape_drive::cache::{Freshness, HttpCache};
+use tape_drive::downloader::Config;
+use tape_drive::mock::{Fault, Fixture, MockServer};
+
+mod common;
+use common::cache_dir;
+
+#[async_std::test]
+async fn revalidates_cached_list() {
+    let dir = cache_dir("cache_revalidate");
+    let cache = HttpCache::new(&dir);
+    let server = MockServer::start(Fixture::default().with_file("/README.md", "# list"))
+        .await
+        .unwrap();
+    let url = format!("{}/README.md", server.url());
+
+    let first = cache.download(&server.config(), &url).await.unwrap();
+    let second = cache.download(&server.config(), &url).await.unwrap();
+    server.set_file("/README.md", "# new list");
+    let third = cache.download(&server.config(), &url).await.unwrap();
+
+    assert_eq!(first.freshness(), Freshness::Changed);
+    assert_eq!(second.freshness(), Freshness::Unchanged);
+    assert_eq!(second.body(), "# list");
+    assert!(third.changed());
+    assert_eq!(third.body(), "# new list");
+    let statuses: Vec<u16> = server.requests().iter().map(|r| r.status).collect();
+    assert_eq!(statuses, vec![200, 304, 200]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[async_std::test]
+async fn falls_back_to_cache_when_unavailable() {
+    let dir = cache_dir("cache_unavailable");
+    let cache = HttpCache::new(&dir);
+    let server = MockServer::start(Fixture::default().with_file("/README.md", "# list"))
+        .await
+        .unwrap();
+    let url = format!("{}/README.md", server.url());
+    cache.download(&server.config(), &url).await.unwrap();
+    server.inject("/README.md", Fault::Status(503));
+
+    let unavailable = cache.download(&server.config(), &url).await.unwrap();
+    drop(server);
+    let offline = cache
+        .download(
+            &Config::new("", "").with_request_timeout(Duration::from_millis(200)),
+            &url,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(unavailable.freshness(), Freshness::Offline);
+    assert_eq!(offline.freshness(), Freshness::Offline);
+    assert_eq!(offline.body(), "# list");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[async_std::test]
+async fn fails_without_cached_copy() {
+    let dir = cache_dir("cache_missing");
+    let server = MockServer::start(Fixture::default().with_file("/README.md", "# list"))
+        .await
+        .unwrap();
+    server.inject("/README.md", Fault::Status(503));
+    let url = format!("{}/README.md", server.url());
+
+    let res = HttpCache::new(&dir).download(&server.config(), &url).await;
+
+    assert!(res.is_err());
+    assert!(!dir.exists());
+}
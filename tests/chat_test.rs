@@ -0,0 +1,237 @@
+#![cfg(feature = "chat")]
+use async_std::{io::BufReader, net::TcpListener, net::TcpStream, prelude::*, task};
+use std::time::Duration;
+use tape_drive::chat::{self, ChatConfig};
+
+/// Reads client lines until the JOIN, returns everything received.
+async fn read_until_join(reader: &mut BufReader<&TcpStream>) -> Vec<String> {
+    let mut received = vec![];
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.unwrap() == 0 {
+            return received;
+        }
+        let joined = line.starts_with("JOIN");
+        received.push(line.trim_end().to_string());
+        if joined {
+            return received;
+        }
+    }
+}
+
+async fn send(mut stream: &TcpStream, lines: &[&str]) {
+    for line in lines {
+        stream
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .await
+            .unwrap();
+    }
+}
+
+fn privmsg(nick: &str, channel: &str, emotes: &str, text: &str) -> String {
+    format!(
+        "@badge-info=;color=#FF0000;display-name={0};emotes={1};mod=0 \
+         :{0}!{0}@{0}.tmi.twitch.tv PRIVMSG #{2} :{3}",
+        nick, emotes, channel, text
+    )
+}
+
+/// Fake TMI: answers a PING, sends RECONNECT on the first connection and
+/// more messages on the second one.
+async fn fake_tmi(listener: TcpListener) -> Vec<String> {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut reader = BufReader::new(&stream);
+    let mut received = read_until_join(&mut reader).await;
+    send(
+        &stream,
+        &[
+            ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            ":justinfan12345!justinfan12345@justinfan12345.tmi.twitch.tv JOIN #streamer_1",
+            &privmsg("ronni", "streamer_1", "25:0-4", "Kappa hello"),
+            &privmsg("ronni", "streamer_1", "", "second message"),
+            &privmsg("lurker", "streamer_2", "", "hi"),
+            "PING :tmi.twitch.tv",
+        ],
+    )
+    .await;
+    let mut pong = String::new();
+    reader.read_line(&mut pong).await.unwrap();
+    received.push(pong.trim_end().to_string());
+    send(&stream, &[":tmi.twitch.tv RECONNECT"]).await;
+    drop(reader);
+    drop(stream);
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut reader = BufReader::new(&stream);
+    received.extend(read_until_join(&mut reader).await);
+    send(
+        &stream,
+        &[
+            &privmsg("dallas", "streamer_1", "25:0-4,6-10", "Kappa Kappa"),
+            &privmsg("lurker", "not_joined", "", "ignored"),
+        ],
+    )
+    .await;
+    // keep the connection open until the client is done
+    let mut line = String::new();
+    while reader.read_line(&mut line).await.unwrap_or(0) > 0 {}
+    received
+}
+
+#[async_std::test]
+async fn records_activity_across_reconnects() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ChatConfig::anonymous()
+        .with_address(listener.local_addr().unwrap().to_string())
+        .with_reconnect_delay(Duration::from_millis(1));
+    let server = task::spawn(fake_tmi(listener));
+    let channels = ["Streamer_1".to_string(), "streamer_2".to_string()];
+
+    let activity = chat::record_activity(
+        &config,
+        channels.iter().collect(),
+        Duration::from_millis(500),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(activity.len(), 2);
+    assert_eq!(activity[0].channel(), "streamer_1");
+    assert_eq!(activity[0].messages(), 3);
+    assert_eq!(activity[0].unique_chatters(), 2);
+    assert_eq!(activity[0].top_emotes(), vec![(&"Kappa".to_string(), 3)]);
+    assert!(activity[0].message_rate() > 60.0);
+    assert_eq!(activity[1].messages(), 1);
+
+    let received = server.await;
+    assert_eq!(
+        received,
+        vec![
+            "CAP REQ :twitch.tv/tags twitch.tv/commands",
+            "NICK justinfan12345",
+            "JOIN #streamer_1,#streamer_2",
+            "PONG :tmi.twitch.tv",
+            "CAP REQ :twitch.tv/tags twitch.tv/commands",
+            "NICK justinfan12345",
+            "JOIN #streamer_1,#streamer_2",
+        ]
+    );
+}
+
+#[async_std::test]
+async fn failed_login_is_an_error() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ChatConfig::anonymous()
+        .with_address(listener.local_addr().unwrap().to_string())
+        .with_login("someone", "oauth:expired");
+    let server = task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(&stream);
+        let received = read_until_join(&mut reader).await;
+        send(
+            &stream,
+            &[":tmi.twitch.tv NOTICE * :Login authentication failed"],
+        )
+        .await;
+        received
+    });
+    let channels = ["streamer_1".to_string()];
+
+    let res =
+        chat::record_activity(&config, channels.iter().collect(), Duration::from_secs(5)).await;
+
+    assert!(res.is_err());
+    assert_eq!(
+        server.await[..2],
+        [
+            "CAP REQ :twitch.tv/tags twitch.tv/commands",
+            "PASS oauth:expired"
+        ]
+    );
+}
+
+#[async_std::test]
+async fn gives_up_after_max_reconnects() {
+    // nothing listens on the port anymore
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let config = ChatConfig::anonymous()
+        .with_address(address)
+        .with_max_reconnects(2)
+        .with_reconnect_delay(Duration::from_millis(1));
+    let channels = ["streamer_1".to_string()];
+
+    let res =
+        chat::record_activity(&config, channels.iter().collect(), Duration::from_secs(5)).await;
+
+    assert!(res.unwrap_err().to_string().contains("lost too often"));
+}
+
+#[async_std::test]
+async fn counts_reconnects_from_last_join() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ChatConfig::anonymous()
+        .with_address(listener.local_addr().unwrap().to_string())
+        .with_max_reconnects(1)
+        .with_reconnect_delay(Duration::from_millis(20));
+    // three sessions that join and end with RECONNECT, then nothing listens
+    let server = task::spawn(async move {
+        for _ in 0..3 {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(&stream);
+            read_until_join(&mut reader).await;
+            send(
+                &stream,
+                &[
+                    ":justinfan12345!justinfan12345@justinfan12345.tmi.twitch.tv JOIN #streamer_1",
+                    &privmsg("ronni", "streamer_1", "", "hello"),
+                    ":tmi.twitch.tv RECONNECT",
+                ],
+            )
+            .await;
+        }
+    });
+    let channels = ["streamer_1".to_string()];
+
+    let res =
+        chat::record_activity(&config, channels.iter().collect(), Duration::from_secs(5)).await;
+    server.await;
+
+    let e = res.unwrap_err();
+    assert!(e.to_string().contains("lost too often"));
+    assert_eq!(e.activity[0].messages(), 3);
+}
+
+#[async_std::test]
+async fn keeps_lines_split_across_joins() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ChatConfig::anonymous()
+        .with_address(listener.local_addr().unwrap().to_string())
+        .with_join_interval(Duration::from_millis(20));
+    let channels: Vec<String> = (0..25).map(|i| format!("streamer_{}", i)).collect();
+    let server = task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(&stream);
+        read_until_join(&mut reader).await;
+        let line = format!("{}\r\n", privmsg("ronni", "streamer_1", "", "hello"));
+        let (first, rest) = line.split_at(line.len() / 2);
+        (&stream).write_all(first.as_bytes()).await.unwrap();
+        // the client wakes up for the second JOIN in between
+        task::sleep(Duration::from_millis(60)).await;
+        (&stream).write_all(rest.as_bytes()).await.unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).await.unwrap_or(0) > 0 {}
+    });
+
+    let activity = chat::record_activity(
+        &config,
+        channels.iter().collect(),
+        Duration::from_millis(200),
+    )
+    .await
+    .unwrap();
+    drop(server);
+
+    assert_eq!(activity[1].messages(), 1);
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the integration tests, each test crate uses a part.
+#![allow(dead_code)]
+
+use serde_json::json;
+use std::path::PathBuf;
+
+/// Empty temporary directory for a cache, `name` unique across tests.
+pub fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tape_drive_test_{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Helix user `streamer_<id>` with every field, without images.
+pub fn user(id: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "login": format!("streamer_{}", id),
+        "display_name": format!("Streamer_{}", id),
+        "type": "",
+        "broadcaster_type": "",
+        "description": "rust",
+        "profile_image_url": "",
+        "offline_image_url": "",
+        "view_count": 0,
+        "created_at": "2016-12-14T20:32:28Z",
+    })
+}
@@ -0,0 +1,1125 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use futures::StreamExt;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tape_drive::cassette::{Cassette, Recorder, SCRUBBED};
+use tape_drive::downloader::{
+    self, BroadcasterType, Diagnostic, DuplicatePolicy, Enrichments, FetchOptions, Strategy,
+    UserType,
+};
+use tape_drive::mock::{Fault, Fixture, MockServer};
+use tape_drive::nom_parser::Streamer;
+use tape_drive::transport::{HttpResponse, MemoryTransport};
+use tape_drive::Error;
+
+mod common;
+
+fn fixture(user_count: usize, followers_per_user: usize) -> Fixture {
+    let mut fixture = Fixture::default();
+    for i in 0..user_count {
+        let mut user = common::user(&i.to_string());
+        user["broadcaster_type"] = "affiliate".into();
+        user["profile_image_url"] = format!("https://example.com/{}.png", i).into();
+        user["view_count"] = (i * 10).into();
+        fixture = fixture.with_user(user);
+        for f in 0..followers_per_user {
+            fixture = fixture.with_follow(json!({
+                "from_id": format!("f{}", f),
+                "to_id": i.to_string(),
+                "followed_at": "2020-05-01T10:00:00Z",
+            }));
+        }
+    }
+    fixture
+}
+
+fn logins(count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("streamer_{}", i)).collect()
+}
+
+fn sequential() -> FetchOptions {
+    FetchOptions::new().with_strategy(Strategy::Sequential)
+}
+
+fn with_followers() -> FetchOptions {
+    FetchOptions::new().with_followers()
+}
+
+fn streamers(count: usize) -> Vec<Streamer> {
+    (0..count)
+        .map(|i| Streamer::new(format!("Streamer {}", i), format!("streamer_{}", i), "rust"))
+        .collect()
+}
+
+#[async_std::test]
+async fn fetch_users_batches_logins() {
+    let server = MockServer::start(fixture(150, 0)).await.unwrap();
+    let streamers = streamers(150);
+
+    let users =
+        downloader::fetch_users(&server.config(), streamers.iter().collect(), &sequential())
+            .await
+            .unwrap();
+
+    assert_eq!(users.users.len(), 150);
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(users.users[42].display_name(), "Streamer_42");
+    assert_eq!(users.users[42].view_count(), 420);
+}
+
+#[async_std::test]
+async fn reports_logins_without_user() {
+    let server = MockServer::start(fixture(3, 1)).await.unwrap();
+    let streamers = [
+        Streamer::new("Streamer 0", "streamer_0", "rust"),
+        Streamer::new("Renamed", "old_login", "go"),
+        Streamer::new("Streamer 2", "Streamer_2", "rust"),
+        Streamer::new("Renamed", "old_login", "go"),
+    ];
+
+    let lookup =
+        downloader::fetch_users(&server.config(), streamers.iter().collect(), &sequential())
+            .await
+            .unwrap();
+    let parallel = downloader::fetch_users(
+        &server.config(),
+        streamers.iter().collect(),
+        &with_followers(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(lookup.users.len(), 2);
+    assert_eq!(lookup.users[1].login(), "streamer_2");
+    assert_eq!(lookup.users[1].streamer_name(), "Streamer 2");
+    assert_eq!(lookup.missing.len(), 1);
+    assert_eq!(lookup.missing[0].login(), "old_login");
+    assert_eq!(lookup.missing[0].name(), "Renamed");
+    assert_eq!(parallel.user("0").unwrap().streamer_name(), "Streamer 0");
+    assert_eq!(parallel.missing, lookup.missing);
+}
+
+#[async_std::test]
+async fn resolves_duplicates_by_policy() {
+    let fixture = fixture(2, 0).with_user(json!({
+        "id": "1",
+        "login": "streamer_1_renamed",
+        "display_name": "Streamer_1_Renamed",
+        "type": "",
+        "broadcaster_type": "",
+        "description": "",
+        "profile_image_url": "",
+        "offline_image_url": "",
+        "view_count": 0,
+        "created_at": "2020-05-01T10:00:00Z",
+    }));
+    let server = MockServer::start(fixture).await.unwrap();
+    let streamers = [
+        Streamer::new("Streamer 0", "streamer_0", "rust"),
+        Streamer::new("Streamer Zero", "Streamer_0", "rust"),
+        Streamer::new("Streamer 1", "streamer_1", "go"),
+        Streamer::new("Streamer 1 renamed", "streamer_1_renamed", "go"),
+    ];
+
+    let first =
+        downloader::fetch_users(&server.config(), streamers.iter().collect(), &sequential())
+            .await
+            .unwrap();
+    let last = downloader::fetch_users(
+        &server
+            .config()
+            .with_duplicate_policy(DuplicatePolicy::KeepLast),
+        streamers.iter().collect(),
+        &with_followers(),
+    )
+    .await
+    .unwrap();
+    let merged = downloader::fetch_users(
+        &server
+            .config()
+            .with_duplicate_policy(DuplicatePolicy::MergeNames),
+        streamers.iter().collect(),
+        &sequential(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(first.users.len(), 2);
+    assert_eq!(first.users[0].streamer_name(), "Streamer 0");
+    assert_eq!(first.users[1].login(), "streamer_1");
+    assert_eq!(last.user("0").unwrap().streamer_name(), "Streamer Zero");
+    assert_eq!(last.user("1").unwrap().login(), "streamer_1_renamed");
+    assert_eq!(
+        merged.users[0].streamer_name(),
+        "Streamer 0 / Streamer Zero"
+    );
+    assert_eq!(
+        first.diagnostics,
+        vec![
+            Diagnostic::DuplicateUser {
+                id: "1".to_string()
+            },
+            Diagnostic::DuplicateEntry {
+                login: "streamer_0".to_string(),
+                names: vec!["Streamer 0".to_string(), "Streamer Zero".to_string()],
+            },
+        ]
+    );
+    // both entries of the renamed user are linked to the user kept
+    assert!(first.missing.is_empty());
+    assert!(last.missing.is_empty());
+    assert_eq!(first.user("1").unwrap().streamer_name(), "Streamer 1");
+    assert_eq!(
+        last.user("1").unwrap().streamer_name(),
+        "Streamer 1 renamed"
+    );
+    assert_eq!(
+        merged.user("1").unwrap().streamer_name(),
+        "Streamer 1 / Streamer 1 renamed"
+    );
+    assert_eq!(last.diagnostics.len(), 2);
+}
+
+#[async_std::test]
+async fn fetch_users_concurrently_with_followers() {
+    let server = MockServer::start(fixture(180, 3)).await.unwrap();
+    server.set_latency(Duration::from_millis(20));
+    let streamers = streamers(180);
+
+    let users = downloader::fetch_users(
+        &server.config(),
+        streamers.iter().collect(),
+        &with_followers(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(users.users.len(), 180);
+    assert_eq!(users.users[7].display_name(), "Streamer_7");
+    assert!(users.users.iter().all(|u| u.follower_count() == 3));
+    assert!(server.max_in_flight() > 1);
+    assert!(server.max_in_flight() <= 4);
+}
+
+#[async_std::test]
+async fn captures_full_user_fields() {
+    let fixture = Fixture::default().with_user(json!({
+        "id": "141981764",
+        "login": "twitchdev",
+        "display_name": "TwitchDev",
+        "type": "staff",
+        "broadcaster_type": "partner",
+        "description": "Supporting third-party developers",
+        "profile_image_url": "https://example.com/profile.png",
+        "offline_image_url": "https://example.com/offline.png",
+        "view_count": 5980557,
+        "email": "not-real@email.com",
+        "created_at": "2016-12-14T20:32:28Z",
+    }));
+    let server = MockServer::start(fixture).await.unwrap();
+    let streamers = [Streamer::new("TwitchDev", "twitchdev", "rust")];
+
+    let users =
+        downloader::fetch_users(&server.config(), streamers.iter().collect(), &sequential())
+            .await
+            .unwrap();
+
+    let user = &users.users[0];
+    assert_eq!(user.user_type(), UserType::Staff);
+    assert_eq!(user.broadcaster_type(), BroadcasterType::Partner);
+    assert_eq!(user.profile_image_url(), "https://example.com/profile.png");
+    assert_eq!(user.offline_image_url(), "https://example.com/offline.png");
+    assert_eq!(user.email().unwrap(), "not-real@email.com");
+    let created_at = Utc.with_ymd_and_hms(2016, 12, 14, 20, 32, 28).unwrap();
+    assert_eq!(user.created_at(), created_at);
+    let now = Utc.with_ymd_and_hms(2020, 12, 14, 20, 32, 28).unwrap();
+    assert_eq!(user.account_age(now).num_days(), 1461);
+
+    let json = serde_json::to_value(user).unwrap();
+    assert_eq!(json["type"], "staff");
+    assert_eq!(json["broadcaster_type"], "partner");
+    assert_eq!(json["created_at"], "2016-12-14T20:32:28Z");
+}
+
+#[async_std::test]
+async fn fetch_users_with_follower_lists() {
+    let mut fixture = fixture(3, 5);
+    for user_id in ["0", "2"] {
+        fixture = fixture.with_follow(json!({
+            "from_id": "fan",
+            "from_login": "fan",
+            "to_id": user_id,
+            "followed_at": "2020-05-02T08:00:00Z",
+        }));
+    }
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_page_size(2);
+    let streamers = streamers(3);
+    let options = sequential().with_follower_lists();
+
+    let lookup = downloader::fetch_users(&server.config(), streamers.iter().collect(), &options)
+        .await
+        .unwrap();
+
+    let user = lookup.user("0").unwrap();
+    let followers = user.followers().unwrap();
+    assert_eq!(followers.len(), 6);
+    assert_eq!(user.follower_count(), 6);
+    assert_eq!(followers[5].login(), "fan");
+    let may_1 = NaiveDate::from_ymd_opt(2020, 5, 1).unwrap();
+    let may_2 = NaiveDate::from_ymd_opt(2020, 5, 2).unwrap();
+    let per_day = downloader::follows_per_day(followers);
+    assert_eq!(
+        per_day.into_iter().collect::<Vec<_>>(),
+        [(may_1, 5), (may_2, 1)]
+    );
+    // f0 to f4 follow everyone
+    let shared = downloader::shared_followers(&lookup.users, 2);
+    assert_eq!(shared.len(), 6);
+    assert_eq!(shared["fan"], ["0", "2"]);
+    assert_eq!(shared["f0"], ["0", "1", "2"]);
+    assert_eq!(downloader::shared_followers(&lookup.users, 3).len(), 5);
+    // three pages per user
+    let follows = server
+        .requests()
+        .iter()
+        .filter(|r| r.path == "/helix/users/follows")
+        .count();
+    assert_eq!(follows, 9);
+}
+
+#[async_std::test]
+async fn follower_count_with_lists_is_helix_total() {
+    let users_url = "https://api.twitch.tv/helix/users?login=streamer_7";
+    let follows_url = "https://api.twitch.tv/helix/users/follows?to_id=7&first=100";
+    // a follow that came in while paging is counted but not listed
+    let follows = json!({
+        "total": 3,
+        "data": [
+            {"from_id": "f0", "to_id": "7", "followed_at": "2020-05-01T10:00:00Z"},
+            {"from_id": "f1", "to_id": "7", "followed_at": "2020-05-01T09:00:00Z"},
+        ],
+        "pagination": {},
+    });
+    let transport = MemoryTransport::new()
+        .with_response(
+            "GET",
+            users_url,
+            HttpResponse::new(200, json!({ "data": [common::user("7")] }).to_string()),
+        )
+        .with_response(
+            "GET",
+            follows_url,
+            HttpResponse::new(200, follows.to_string()),
+        );
+    let config = downloader::Config::new("id", "token").with_transport(transport);
+    let streamers = [Streamer::new("Streamer 7", "streamer_7", "rust")];
+    let options = sequential().with_follower_lists();
+
+    let lookup = downloader::fetch_users(&config, streamers.iter().collect(), &options)
+        .await
+        .unwrap();
+
+    let user = lookup.user("7").unwrap();
+    assert_eq!(user.follower_count(), 3);
+    assert_eq!(user.followers().unwrap().len(), 2);
+}
+
+#[async_std::test]
+async fn fetch_users_pipelines_follower_lookups() {
+    let server = MockServer::start(fixture(300, 1)).await.unwrap();
+    let streamers = streamers(300);
+    let options = with_followers().with_strategy(Strategy::Concurrent { workers: 2 });
+
+    let users = downloader::fetch_users(&server.config(), streamers.iter().collect(), &options)
+        .await
+        .unwrap();
+
+    assert_eq!(users.users.len(), 300);
+    assert!(users.users.iter().all(|u| u.follower_count() == 1));
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    let first_follows = paths.iter().position(|p| p == "/helix/users/follows");
+    let last_users = paths.iter().rposition(|p| p == "/helix/users");
+    assert!(first_follows.unwrap() < last_users.unwrap());
+    assert!(server.max_in_flight() <= 2);
+}
+
+#[async_std::test]
+async fn stream_users_yields_batches_with_followers() {
+    let server = MockServer::start(fixture(250, 2)).await.unwrap();
+    let streamers = streamers(250);
+    let config = server.config();
+
+    let users: Vec<_> =
+        downloader::stream_users(&config, streamers.iter().collect(), &with_followers())
+            .collect()
+            .await;
+
+    let users: Vec<_> = users.into_iter().map(Result::unwrap).collect();
+    assert_eq!(users.len(), 250);
+    let ids: HashSet<&String> = users.iter().map(|u| u.id()).collect();
+    assert_eq!(ids.len(), 250);
+    assert!(users.iter().all(|u| u.follower_count() == 2));
+    let user = users.iter().find(|u| u.id() == "7").unwrap();
+    assert_eq!(user.streamer_name(), "Streamer 7");
+}
+
+#[async_std::test]
+async fn stream_users_shares_workers_with_enrichments() {
+    let server = MockServer::start(fixture(250, 1)).await.unwrap();
+    server.set_latency(Duration::from_millis(10));
+    let streamers = streamers(250);
+    let config = server.config();
+    let options = with_followers()
+        .with_streams()
+        .with_strategy(Strategy::Concurrent { workers: 3 });
+
+    let users: Vec<_> = downloader::stream_users(&config, streamers.iter().collect(), &options)
+        .collect()
+        .await;
+
+    assert_eq!(users.len(), 250);
+    assert!(users
+        .iter()
+        .all(|u| u.as_ref().unwrap().follower_count() == 1));
+    assert!(server.max_in_flight() > 1);
+    assert!(server.max_in_flight() <= 3);
+}
+
+#[async_std::test]
+async fn retries_rate_limited_and_server_errors() {
+    let server = MockServer::start(fixture(3, 0)).await.unwrap();
+    server.inject("/helix/users", Fault::RateLimited);
+    server.inject("/helix/users", Fault::Status(503));
+    let streamers = streamers(3);
+
+    let users =
+        downloader::fetch_users(&server.config(), streamers.iter().collect(), &sequential())
+            .await
+            .unwrap();
+
+    assert_eq!(users.users.len(), 3);
+    let statuses: Vec<u16> = server.requests().iter().map(|r| r.status).collect();
+    assert_eq!(statuses, vec![429, 503, 200]);
+}
+
+#[async_std::test]
+async fn fetch_users_with_all_enrichments() {
+    let fixture = fixture(3, 2)
+        .with_stream(json!({
+            "id": "s1",
+            "user_id": "1",
+            "user_login": "streamer_1",
+            "user_name": "Streamer_1",
+            "game_id": "1469308723",
+            "game_name": "Science & Technology",
+            "type": "live",
+            "title": "async rust",
+            "viewer_count": 42,
+            "started_at": "2020-05-21T15:42:00Z",
+            "language": "en",
+            "thumbnail_url": "",
+        }))
+        .with_channel(json!({
+            "broadcaster_id": "2",
+            "broadcaster_name": "Streamer_2",
+            "broadcaster_language": "de",
+            "game_id": "509670",
+            "game_name": "Software and Game Development",
+            "title": "nom parsers",
+        }));
+    let server = MockServer::start(fixture).await.unwrap();
+    let streamers = streamers(3);
+    let options = FetchOptions::new()
+        .with_strategy(Strategy::Concurrent { workers: 2 })
+        .with_enrichments(Enrichments::all());
+
+    let lookup = downloader::fetch_users(&server.config(), streamers.iter().collect(), &options)
+        .await
+        .unwrap();
+
+    assert_eq!(lookup.users.len(), 3);
+    assert!(lookup.users.iter().all(|u| u.follower_count() == 2));
+    assert!(lookup.users[0].live_stream().is_none());
+    assert_eq!(lookup.users[1].live_stream().unwrap().viewer_count(), 42);
+    assert!(lookup.users[1].channel().is_none());
+    assert_eq!(
+        lookup.users[2].channel().unwrap().broadcaster_language(),
+        "de"
+    );
+    assert!(lookup.unfinished.is_empty());
+    assert!(server.max_in_flight() <= 2);
+    // the enrichments are part of the output and read back from it
+    let output = serde_json::to_value(&lookup.users[1]).unwrap();
+    assert_eq!(output["follower_count"], 2);
+    assert_eq!(output["streamer_name"], "Streamer 1");
+    assert_eq!(output["live_stream"]["viewer_count"], 42);
+    let user: downloader::TwitchUserData = serde_json::from_value(output).unwrap();
+    assert_eq!(user.live_stream().unwrap().title(), "async rust");
+}
+
+#[async_std::test]
+async fn fails_after_max_retries() {
+    let server = MockServer::start(fixture(3, 0)).await.unwrap();
+    for _ in 0..3 {
+        server.inject("/helix/users", Fault::Status(500));
+    }
+    let streamers = streamers(3);
+
+    let res = downloader::fetch_users(
+        &server.config().with_max_retries(2),
+        streamers.iter().collect(),
+        &with_followers(),
+    )
+    .await;
+
+    assert!(matches!(res, Err(Error::Http { status: 500, .. })));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[async_std::test]
+async fn request_timeout_is_an_error() {
+    let server = MockServer::start(fixture(1, 0)).await.unwrap();
+    server.set_latency(Duration::from_millis(500));
+    let logins = logins(1);
+
+    let res = downloader::get_live_streams(
+        &server
+            .config()
+            .with_request_timeout(Duration::from_millis(20)),
+        logins.iter().collect(),
+    )
+    .await;
+
+    assert!(res.unwrap_err().is_timeout());
+}
+
+#[async_std::test]
+async fn crawl_deadline_returns_partial_users() {
+    let server = MockServer::start(fixture(150, 0)).await.unwrap();
+    server.set_latency(Duration::from_millis(100));
+    let streamers = streamers(150);
+
+    let lookup = downloader::fetch_users(
+        &server
+            .config()
+            .with_crawl_deadline(Duration::from_millis(170)),
+        streamers.iter().collect(),
+        &sequential(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(lookup.users.len(), 100);
+    assert_eq!(lookup.unfinished.len(), 50);
+    assert_eq!(lookup.unfinished[0], "streamer_100");
+    assert!(lookup.missing.is_empty());
+}
+
+#[async_std::test]
+async fn crawl_deadline_cuts_off_followers() {
+    let server = MockServer::start(fixture(60, 1)).await.unwrap();
+    server.set_latency(Duration::from_millis(100));
+    let streamers = streamers(60);
+
+    let lookup = downloader::fetch_users(
+        &server
+            .config()
+            .with_crawl_deadline(Duration::from_millis(250)),
+        streamers.iter().collect(),
+        &with_followers(),
+    )
+    .await
+    .unwrap();
+
+    // the single user batch returns first, then the follower lookups run on
+    // the four shared workers, each starting as another one ends, until the
+    // deadline leaves the rest unfinished
+    assert_eq!(lookup.users.len(), 60);
+    let with_followers = lookup
+        .users
+        .iter()
+        .filter(|u| u.follower_count() == 1)
+        .count();
+    assert!(with_followers > 0 && with_followers < 60);
+    assert_eq!(with_followers + lookup.unfinished.len(), 60);
+    assert!(lookup.missing.is_empty());
+}
+
+#[async_std::test]
+async fn rejects_wrong_credentials() {
+    let server = MockServer::start(fixture(1, 0)).await.unwrap();
+    let config =
+        downloader::Config::new("mock-client-id", "expired").with_helix_url(server.helix_url());
+    let streamers = streamers(1);
+
+    let res = downloader::fetch_users(&config, streamers.iter().collect(), &sequential()).await;
+
+    assert!(matches!(res, Err(Error::Auth { status: 401, .. })));
+    assert_eq!(server.requests()[0].status, 401);
+}
+
+#[async_std::test]
+async fn tells_rate_limits_from_malformed_responses() {
+    let streams_url = "https://api.twitch.tv/helix/streams?user_login=ann&first=100";
+    let users_url = "https://api.twitch.tv/helix/users?login=ann";
+    let transport = MemoryTransport::new()
+        .with_response(
+            "GET",
+            streams_url,
+            HttpResponse::new(429, "").with_header("Ratelimit-Reset", "1590075720"),
+        )
+        .with_response("GET", users_url, HttpResponse::new(200, "<html>"));
+    let config = downloader::Config::new("id", "token")
+        .with_max_retries(0)
+        .with_transport(transport);
+    let login = "ann".to_string();
+    let streamers = [Streamer::new("Ann", "ann", "rust")];
+
+    let streams = downloader::get_live_streams(&config, vec![&login]).await;
+    let users = downloader::fetch_users(&config, streamers.iter().collect(), &sequential()).await;
+
+    match streams.unwrap_err() {
+        Error::RateLimited { reset, .. } => {
+            assert_eq!(reset, Utc.with_ymd_and_hms(2020, 5, 21, 15, 42, 0).single())
+        }
+        e => panic!("not rate limited: {}", e),
+    }
+    match users.unwrap_err() {
+        Error::Decode { excerpt, .. } => assert_eq!(excerpt, "<html>"),
+        e => panic!("not a decode error: {}", e),
+    }
+}
+
+#[async_std::test]
+async fn app_access_token_from_client_credentials() {
+    let fixture = fixture(1, 0);
+    let secret = fixture.client_secret().to_string();
+    let server = MockServer::start(fixture).await.unwrap();
+    let config = downloader::Config::new("mock-client-id", "")
+        .with_helix_url(server.helix_url())
+        .with_auth_url(server.auth_url())
+        .with_app_access_token(&secret)
+        .await
+        .unwrap();
+    let streamers = streamers(1);
+
+    let users = downloader::fetch_users(&config, streamers.iter().collect(), &sequential())
+        .await
+        .unwrap();
+
+    assert_eq!(users.users.len(), 1);
+}
+
+#[async_std::test]
+async fn replays_recorded_crawl_offline() {
+    let server = MockServer::start(fixture(120, 2)).await.unwrap();
+    server.inject("/helix/users/follows", Fault::RateLimited);
+    let recorder = Recorder::new();
+    let streamers = streamers(120);
+    let recorded = downloader::fetch_users(
+        &server.config().with_recorder(recorder.clone()),
+        streamers.iter().collect(),
+        &with_followers(),
+    )
+    .await
+    .unwrap();
+    let helix_url = server.helix_url();
+    drop(server);
+
+    let path = std::env::temp_dir().join("tape_drive_replay_test.json");
+    recorder.save(&path).unwrap();
+    let cassette = Cassette::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(cassette
+        .interactions()
+        .iter()
+        .flat_map(|i| i.request_headers.iter())
+        .all(|(_, value)| value == SCRUBBED));
+
+    let config = downloader::Config::new("", "")
+        .with_helix_url(helix_url)
+        .with_replay(cassette);
+    let replayed = downloader::fetch_users(&config, streamers.iter().collect(), &with_followers())
+        .await
+        .unwrap();
+
+    assert_eq!(replayed.users.len(), recorded.users.len());
+    for user in recorded.users {
+        let replayed = replayed.user(user.id()).unwrap();
+        assert_eq!(replayed.display_name(), user.display_name());
+        assert_eq!(replayed.follower_count(), user.follower_count());
+    }
+}
+
+#[async_std::test]
+async fn get_live_streams_of_listed_streamers() {
+    let fixture = fixture(150, 0)
+        .with_stream(json!({
+            "id": "s1",
+            "user_id": "3",
+            "user_login": "streamer_3",
+            "user_name": "Streamer_3",
+            "game_id": "1469308723",
+            "game_name": "Science & Technology",
+            "type": "live",
+            "title": "writing a parser with nom",
+            "viewer_count": 42,
+            "started_at": "2020-05-21T15:42:00Z",
+            "language": "en",
+            "thumbnail_url": "https://example.com/streamer_3-{width}x{height}.jpg",
+        }))
+        .with_stream(json!({
+            "id": "s2",
+            "user_id": "142",
+            "user_login": "streamer_142",
+            "user_name": "Streamer_142",
+            "game_id": "509670",
+            "game_name": "Software and Game Development",
+            "type": "live",
+            "title": "async rust",
+            "viewer_count": 7,
+            "started_at": "2020-05-21T16:00:00Z",
+            "language": "de",
+            "thumbnail_url": "https://example.com/streamer_142-{width}x{height}.jpg",
+        }))
+        .with_stream(json!({
+            "id": "s3",
+            "user_id": "999",
+            "user_login": "not_listed",
+            "user_name": "not_listed",
+            "game_id": "",
+            "game_name": "",
+            "type": "live",
+            "title": "",
+            "viewer_count": 1,
+            "started_at": "2020-05-21T16:00:00Z",
+            "language": "en",
+            "thumbnail_url": "",
+        }));
+    let server = MockServer::start(fixture).await.unwrap();
+    let logins = logins(150);
+
+    let streams = downloader::get_live_streams(&server.config(), logins.iter().collect())
+        .await
+        .unwrap();
+
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(streams.len(), 2);
+    assert_eq!(streams[0].user_login(), "streamer_3");
+    assert_eq!(streams[0].game_name(), "Science & Technology");
+    assert_eq!(streams[0].viewer_count(), 42);
+    assert_eq!(
+        streams[0].started_at().to_rfc3339(),
+        "2020-05-21T15:42:00+00:00"
+    );
+    assert_eq!(
+        streams[0].thumbnail_url(320, 180),
+        "https://example.com/streamer_3-320x180.jpg"
+    );
+    assert_eq!(streams[1].language(), "de");
+}
+
+#[async_std::test]
+async fn sends_requests_through_custom_transport() {
+    let url = "https://api.twitch.tv/helix/streams?user_login=ann&first=100";
+    let transport = MemoryTransport::new()
+        .with_response("GET", url, HttpResponse::new(429, ""))
+        .with_response(
+            "GET",
+            url,
+            HttpResponse::new(200, r#"{"data":[],"pagination":{}}"#),
+        )
+        .with_response(
+            "GET",
+            "https://example.com/README.md",
+            HttpResponse::new(200, "# list"),
+        );
+    let config = downloader::Config::new("id", "token")
+        .with_retry_backoff(Duration::from_millis(1))
+        .with_transport(transport.clone());
+    let login = "ann".to_string();
+
+    let streams = downloader::get_live_streams(&config, vec![&login])
+        .await
+        .unwrap();
+    let list = downloader::download_file(&config, "https://example.com/README.md")
+        .await
+        .unwrap();
+
+    assert!(streams.is_empty());
+    assert_eq!(list, "# list");
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[1].header("Authorization"), Some("Bearer token"));
+    assert_eq!(requests[1].header("Client-ID"), Some("id"));
+    assert_eq!(requests[2].header("Authorization"), None);
+}
+
+#[async_std::test]
+async fn get_live_streams_follows_pagination() {
+    let mut fixture = fixture(10, 0);
+    for i in 0..10 {
+        fixture = fixture.with_stream(json!({
+            "id": format!("s{}", i),
+            "user_id": i.to_string(),
+            "user_login": format!("streamer_{}", i),
+            "user_name": format!("Streamer_{}", i),
+            "game_id": "",
+            "game_name": "",
+            "type": "live",
+            "title": "",
+            "viewer_count": i,
+            "started_at": "2020-05-21T16:00:00Z",
+            "language": "en",
+            "thumbnail_url": "",
+        }));
+    }
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_page_size(3);
+    let logins = logins(10);
+
+    let streams = downloader::get_live_streams(&server.config(), logins.iter().collect())
+        .await
+        .unwrap();
+
+    assert_eq!(streams.len(), 10);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[async_std::test]
+async fn get_videos_pages_through_broadcasts() {
+    let mut fixture = fixture(2, 0);
+    for day in 1..=25 {
+        fixture = fixture.with_video(json!({
+            "id": format!("v{}", day),
+            "user_id": "1",
+            "title": format!("stream day {}", day),
+            "published_at": format!("2020-04-{:02}T18:00:00Z", day),
+            "view_count": day,
+            "duration": "2h30m0s",
+            "type": if day % 5 == 0 { "highlight" } else { "archive" },
+            "url": format!("https://www.twitch.tv/videos/v{}", day),
+        }));
+    }
+    fixture = fixture
+        .with_video(json!({
+            "id": "u1",
+            "user_id": "1",
+            "title": "uploaded talk",
+            "published_at": "2020-05-01T18:00:00Z",
+            "view_count": 3,
+            "duration": "45m",
+            "type": "upload",
+            "url": "https://www.twitch.tv/videos/u1",
+        }))
+        .with_video(json!({
+            "id": "other",
+            "user_id": "0",
+            "title": "other streamer",
+            "published_at": "2020-05-01T18:00:00Z",
+            "view_count": 3,
+            "duration": "1h",
+            "type": "archive",
+            "url": "https://www.twitch.tv/videos/other",
+        }));
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_page_size(10);
+
+    let videos = downloader::get_videos(&server.config(), "1").await.unwrap();
+
+    assert_eq!(videos.len(), 25);
+    assert_eq!(server.requests().len(), 3);
+    assert_eq!(videos[0].duration(), Duration::from_secs(9000));
+    assert_eq!(videos[4].video_type(), downloader::VideoType::Highlight);
+
+    let activity = downloader::BroadcastActivity::from_videos(
+        &videos,
+        Utc.with_ymd_and_hms(2020, 4, 21, 0, 0, 0).unwrap(),
+    );
+    assert_eq!(activity.broadcasts, 4);
+    assert_eq!(activity.total_duration, Duration::from_secs(4 * 9000));
+    assert_eq!(
+        activity.last_broadcast,
+        Some(Utc.with_ymd_and_hms(2020, 4, 24, 18, 0, 0).unwrap())
+    );
+    assert!(activity.inactive_since(Utc.with_ymd_and_hms(2020, 5, 1, 0, 0, 0).unwrap()));
+}
+
+fn clip(broadcaster_id: &str, id: usize, view_count: u32, created_at: &str) -> serde_json::Value {
+    json!({
+        "id": format!("clip_{}_{}", broadcaster_id, id),
+        "url": format!("https://clips.twitch.tv/clip_{}_{}", broadcaster_id, id),
+        "broadcaster_id": broadcaster_id,
+        "broadcaster_name": format!("Streamer_{}", broadcaster_id),
+        "creator_id": "c1",
+        "creator_name": "clipper",
+        "title": format!("clip {}", id),
+        "view_count": view_count,
+        "created_at": created_at,
+    })
+}
+
+#[async_std::test]
+async fn get_top_clips_in_time_window() {
+    let mut fixture = fixture(2, 0).with_clip(clip("1", 99, 1000, "2020-04-01T10:00:00Z"));
+    for i in 0..12 {
+        fixture = fixture.with_clip(clip("1", i, i as u32 * 10, "2020-05-18T10:00:00Z"));
+    }
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_page_size(4);
+
+    let clips = downloader::get_top_clips(
+        &server.config(),
+        "1",
+        Utc.with_ymd_and_hms(2020, 5, 17, 0, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(2020, 5, 24, 0, 0, 0).unwrap(),
+        6,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(clips.len(), 6);
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(clips[0].view_count(), 110);
+    assert_eq!(clips[0].title(), "clip 11");
+    assert_eq!(clips[5].view_count(), 60);
+    assert_eq!(clips[0].creator_name(), "clipper");
+}
+
+#[async_std::test]
+async fn merges_top_clips_of_several_broadcasters() {
+    let fixture = fixture(3, 0)
+        .with_clip(clip("0", 0, 5, "2020-05-18T10:00:00Z"))
+        .with_clip(clip("1", 0, 50, "2020-05-18T10:00:00Z"))
+        .with_clip(clip("1", 1, 40, "2020-05-18T10:00:00Z"))
+        .with_clip(clip("2", 0, 20, "2020-05-18T10:00:00Z"));
+    let server = MockServer::start(fixture).await.unwrap();
+    let ids: Vec<String> = (0..3).map(|i| i.to_string()).collect();
+
+    let clips = downloader::get_top_clips_for_broadcasters(
+        &server.config(),
+        ids.iter().collect(),
+        Utc.with_ymd_and_hms(2020, 5, 17, 0, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(2020, 5, 24, 0, 0, 0).unwrap(),
+        3,
+    )
+    .await
+    .unwrap();
+
+    let views: Vec<u32> = clips.iter().map(|c| c.view_count()).collect();
+    assert_eq!(views, vec![50, 40, 20, 5]);
+}
+
+fn tag(name: &str) -> serde_json::Value {
+    json!({
+        "tag_id": name.to_lowercase(),
+        "is_auto": false,
+        "localization_names": { "en-us": name, "de-de": format!("{} (de)", name) },
+        "localization_descriptions": { "en-us": "" },
+    })
+}
+
+#[async_std::test]
+async fn get_channels_with_stream_tags() {
+    let mut fixture = fixture(120, 0);
+    for i in 0..120 {
+        fixture = fixture.with_channel(json!({
+            "broadcaster_id": i.to_string(),
+            "broadcaster_name": format!("Streamer_{}", i),
+            "broadcaster_language": "en",
+            "game_id": if i == 1 { "509658" } else { "1469308723" },
+            "game_name": if i == 1 { "Just Chatting" } else { "Science & Technology" },
+            "title": "building a twitch crawler in Rust",
+            "tags": if i == 3 { json!(["Rust", "English"]) } else { json!([]) },
+        }));
+    }
+    fixture = fixture
+        .with_stream_tag("1", tag("English"))
+        .with_stream_tag("1", tag("Programming"))
+        .with_stream_tag("2", tag("Web Development"))
+        .with_stream_tag("3", tag("Deprecated"));
+    let server = MockServer::start(fixture).await.unwrap();
+    let ids: Vec<String> = (0..120).map(|i| i.to_string()).collect();
+
+    let channels = downloader::get_channels(&server.config(), ids.iter().collect())
+        .await
+        .unwrap();
+
+    assert_eq!(channels.len(), 120);
+    assert_eq!(
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.path == "/helix/channels")
+            .count(),
+        2
+    );
+    let channel = &channels[1];
+    assert_eq!(channel.broadcaster_language(), "en");
+    assert_eq!(channel.game_name(), "Just Chatting");
+    assert_eq!(channel.tags(), &vec!["English", "Programming"]);
+    assert!(!channel.is_development_category());
+    assert!(channels[2].is_development_category());
+    assert_eq!(
+        channels[2].matching_topics(&["JavaScript", "web development", "rust"]),
+        vec!["web development", "rust"]
+    );
+    // the channel's own tags win over the stream tags
+    assert_eq!(channels[3].tags(), &vec!["Rust", "English"]);
+    let tag_lookups = server
+        .requests()
+        .iter()
+        .filter(|r| r.path == "/helix/streams/tags")
+        .count();
+    assert_eq!(tag_lookups, 119);
+}
+
+fn channel(id: usize) -> serde_json::Value {
+    json!({
+        "broadcaster_id": id.to_string(),
+        "broadcaster_name": format!("Streamer_{}", id),
+        "broadcaster_language": "en",
+        "game_id": "1469308723",
+        "game_name": "Science & Technology",
+        "title": "nom parsers",
+    })
+}
+
+#[async_std::test]
+async fn sequential_channels_send_one_request_at_a_time() {
+    let mut fixture = fixture(10, 0);
+    for i in 0..10 {
+        fixture = fixture
+            .with_channel(channel(i))
+            .with_stream_tag(&i.to_string(), tag("Programming"));
+    }
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_latency(Duration::from_millis(10));
+    let streamers = streamers(10);
+    let options = sequential().with_enrichments(Enrichments {
+        channels: true,
+        ..Enrichments::none()
+    });
+
+    let lookup = downloader::fetch_users(&server.config(), streamers.iter().collect(), &options)
+        .await
+        .unwrap();
+
+    assert!(lookup
+        .users
+        .iter()
+        .all(|u| u.channel().unwrap().tags() == &vec!["Programming"]));
+    assert_eq!(server.max_in_flight(), 1);
+}
+
+#[async_std::test]
+async fn stream_tag_lookups_share_workers() {
+    let mut fixture = fixture(10, 0);
+    for i in 0..10 {
+        fixture = fixture
+            .with_channel(channel(i))
+            .with_stream_tag(&i.to_string(), tag("Programming"));
+    }
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_latency(Duration::from_millis(10));
+    let streamers = streamers(10);
+    let options = FetchOptions::new()
+        .with_strategy(Strategy::Concurrent { workers: 3 })
+        .with_enrichments(Enrichments {
+            channels: true,
+            ..Enrichments::none()
+        });
+
+    let lookup = downloader::fetch_users(&server.config(), streamers.iter().collect(), &options)
+        .await
+        .unwrap();
+
+    assert!(lookup
+        .users
+        .iter()
+        .all(|u| u.channel().unwrap().tags() == &vec!["Programming"]));
+    // the ten tag lookups run as pipeline jobs, three at a time
+    assert_eq!(server.max_in_flight(), 3);
+}
+
+#[async_std::test]
+async fn failed_stream_tags_leave_channel_untagged() {
+    let fixture = fixture(2, 0)
+        .with_channel(channel(0))
+        .with_stream_tag("0", tag("Programming"));
+    let server = MockServer::start(fixture).await.unwrap();
+    server.inject("/helix/streams/tags", Fault::Status(404));
+    let id = "0".to_string();
+
+    let channels = downloader::get_channels(&server.config(), vec![&id])
+        .await
+        .unwrap();
+
+    assert_eq!(channels.len(), 1);
+    assert!(channels[0].tags().is_empty());
+}
+
+fn segment(id: &str, start_time: &str, canceled: bool) -> serde_json::Value {
+    json!({
+        "id": id,
+        "start_time": start_time,
+        "end_time": start_time.replace("T18", "T20"),
+        "title": "Rust, async and nom",
+        "canceled_until": if canceled { json!(start_time) } else { json!(null) },
+        "category": { "id": "1469308723", "name": "Science & Technology" },
+        "is_recurring": true,
+    })
+}
+
+#[async_std::test]
+async fn get_schedules_and_export_ical() {
+    let fixture = fixture(3, 0)
+        .with_schedule(json!({
+            "broadcaster_id": "0",
+            "broadcaster_name": "Streamer_0",
+            "broadcaster_login": "streamer_0",
+            "segments": [
+                segment("seg_a", "2020-06-01T18:00:00Z", false),
+                segment("seg_b", "2020-06-02T18:00:00Z", true),
+                segment("seg_c", "2020-06-03T18:00:00Z", false),
+                segment("seg_d", "2020-06-10T18:00:00Z", false),
+            ],
+            "vacation": {
+                "start_time": "2020-06-08T00:00:00Z",
+                "end_time": "2020-06-15T00:00:00Z",
+            },
+        }))
+        .with_schedule(json!({
+            "broadcaster_id": "2",
+            "broadcaster_name": "Streamer_2",
+            "broadcaster_login": "streamer_2",
+            "segments": null,
+            "vacation": null,
+        }));
+    let server = MockServer::start(fixture).await.unwrap();
+    server.set_page_size(3);
+    let ids: Vec<String> = (0..3).map(|i| i.to_string()).collect();
+
+    let schedules = downloader::get_schedules(&server.config(), ids.iter().collect())
+        .await
+        .unwrap();
+
+    assert_eq!(schedules.len(), 2);
+    assert_eq!(schedules[0].segments().len(), 4);
+    let upcoming: Vec<&String> = schedules[0].upcoming_segments().map(|s| s.id()).collect();
+    assert_eq!(upcoming, vec!["seg_a", "seg_c"]);
+    assert!(schedules[0].segments()[0].is_recurring());
+    assert!(schedules[1].segments().is_empty());
+
+    let ical = tape_drive::ical::schedules_to_ical(&schedules);
+    assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(ical.ends_with("END:VCALENDAR\r\n"));
+    assert_eq!(ical.matches("BEGIN:VEVENT").count(), 3);
+    assert!(ical.contains("UID:seg_a@twitch.tv\r\n"));
+    assert!(ical.contains("DTSTART:20200601T180000Z\r\nDTEND:20200601T200000Z\r\n"));
+    assert!(ical.contains("SUMMARY:Streamer_0: Rust\\, async and nom\r\n"));
+    assert!(ical.contains("CATEGORIES:Science & Technology\r\n"));
+    assert!(ical.contains("SUMMARY:Streamer_0: vacation\r\n"));
+}
@@ -0,0 +1,213 @@
+use chrono::{SecondsFormat, Utc};
+use futures::stream::StreamExt;
+use serde_json::{json, Value};
+use tape_drive::eventsub::{self, Event, WebhookListener};
+use tape_drive::mock::{Fixture, MockServer};
+
+const SECRET: &str = "s3cr3t-for-tests";
+
+/// Posts `message` to the listener like Twitch would, signed with `secret`.
+async fn deliver(
+    listener: &WebhookListener,
+    secret: &str,
+    message_id: &str,
+    message_type: &str,
+    message: &Value,
+) -> (u16, String) {
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
+    let body = message.to_string();
+    let signature = eventsub::signature(secret, message_id, &timestamp, body.as_bytes());
+    let mut res = surf::post(listener.url())
+        .set_header("Twitch-Eventsub-Message-Id".parse().unwrap(), message_id)
+        .set_header(
+            "Twitch-Eventsub-Message-Timestamp".parse().unwrap(),
+            timestamp,
+        )
+        .set_header(
+            "Twitch-Eventsub-Message-Signature".parse().unwrap(),
+            signature,
+        )
+        .set_header(
+            "Twitch-Eventsub-Message-Type".parse().unwrap(),
+            message_type,
+        )
+        .body_string(body)
+        .await
+        .unwrap();
+    (res.status().into(), res.body_string().await.unwrap())
+}
+
+fn notification(subscription_type: &str, event: Value) -> Value {
+    json!({
+        "subscription": {
+            "id": "sub-1",
+            "status": "enabled",
+            "type": subscription_type,
+            "version": "1",
+            "condition": { "broadcaster_user_id": "1" },
+        },
+        "event": event,
+    })
+}
+
+#[async_std::test]
+async fn create_subscriptions_for_every_broadcaster() {
+    let server = MockServer::start(Fixture::default()).await.unwrap();
+    let ids = ["1".to_string(), "2".to_string()];
+
+    let subscriptions = eventsub::create_subscriptions(
+        &server.config(),
+        ids.iter().collect(),
+        "https://example.com/eventsub",
+        SECRET,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(subscriptions.len(), 6);
+    assert_eq!(subscriptions[3].broadcaster_user_id(), "2");
+    assert_eq!(subscriptions[3].subscription_type(), "stream.online");
+    assert_eq!(server.subscriptions()[5]["type"], "channel.update");
+
+    // existing subscriptions are skipped
+    let subscriptions = eventsub::create_subscriptions(
+        &server.config(),
+        ids.iter().collect(),
+        "https://example.com/eventsub",
+        SECRET,
+    )
+    .await
+    .unwrap();
+    assert!(subscriptions.is_empty());
+    assert_eq!(server.subscriptions().len(), 6);
+}
+
+#[async_std::test]
+async fn listener_answers_challenge() {
+    let (listener, _events) = WebhookListener::bind("127.0.0.1:0", SECRET).await.unwrap();
+    let message = json!({
+        "challenge": "pogchamp-kappa-360noscope-vohiyo",
+        "subscription": { "type": "stream.online" },
+    });
+
+    let (status, body) = deliver(
+        &listener,
+        SECRET,
+        "m-1",
+        "webhook_callback_verification",
+        &message,
+    )
+    .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body, "pogchamp-kappa-360noscope-vohiyo");
+}
+
+#[async_std::test]
+async fn listener_emits_verified_events_once() {
+    let (listener, mut events) = WebhookListener::bind("127.0.0.1:0", SECRET).await.unwrap();
+    let online = notification(
+        "stream.online",
+        json!({
+            "id": "9001",
+            "broadcaster_user_id": "1",
+            "broadcaster_user_login": "streamer_1",
+            "broadcaster_user_name": "Streamer_1",
+            "type": "live",
+            "started_at": "2020-10-11T10:11:12.123Z",
+        }),
+    );
+    let update = notification(
+        "channel.update",
+        json!({
+            "broadcaster_user_id": "1",
+            "broadcaster_user_login": "streamer_1",
+            "broadcaster_user_name": "Streamer_1",
+            "title": "Writing an EventSub listener",
+            "language": "en",
+            "category_id": "1469308723",
+            "category_name": "Software and Game Development",
+        }),
+    );
+
+    assert_eq!(
+        deliver(&listener, SECRET, "m-1", "notification", &online)
+            .await
+            .0,
+        204
+    );
+    // Twitch retries with the same message id, the duplicate is acknowledged
+    assert_eq!(
+        deliver(&listener, SECRET, "m-1", "notification", &online)
+            .await
+            .0,
+        204
+    );
+    assert_eq!(
+        deliver(&listener, "wrong-secret", "m-2", "notification", &update)
+            .await
+            .0,
+        403
+    );
+    assert_eq!(
+        deliver(&listener, SECRET, "m-3", "notification", &update)
+            .await
+            .0,
+        204
+    );
+
+    match events.next().await.unwrap() {
+        Event::StreamOnline(online) => {
+            assert_eq!(online.broadcaster_user_login(), "streamer_1");
+            assert_eq!(online.stream_type(), "live");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    match events.next().await.unwrap() {
+        Event::ChannelUpdate(update) => {
+            assert_eq!(update.title(), "Writing an EventSub listener");
+            assert_eq!(update.category_id(), "1469308723");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[async_std::test]
+async fn listener_emits_revocations() {
+    let (listener, mut events) = WebhookListener::bind("127.0.0.1:0", SECRET).await.unwrap();
+    let mut revocation = notification("stream.offline", Value::Null);
+    revocation["subscription"]["status"] = json!("user_removed");
+
+    let (status, _) = deliver(&listener, SECRET, "m-1", "revocation", &revocation).await;
+
+    assert_eq!(status, 204);
+    match events.next().await.unwrap() {
+        Event::Revoked(subscription) => assert_eq!(subscription.status(), "user_removed"),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[async_std::test]
+async fn listener_accepts_retry_of_rejected_message() {
+    let (listener, mut events) = WebhookListener::bind("127.0.0.1:0", SECRET).await.unwrap();
+    let malformed = notification("stream.offline", json!({ "broadcaster_user_id": 1 }));
+    let offline = notification(
+        "stream.offline",
+        json!({
+            "broadcaster_user_id": "1",
+            "broadcaster_user_login": "streamer_1",
+            "broadcaster_user_name": "Streamer_1",
+        }),
+    );
+
+    let (rejected, _) = deliver(&listener, SECRET, "m-1", "notification", &malformed).await;
+    let (retried, _) = deliver(&listener, SECRET, "m-1", "notification", &offline).await;
+
+    assert_eq!((rejected, retried), (400, 204));
+    match events.next().await.unwrap() {
+        Event::StreamOffline(offline) => {
+            assert_eq!(offline.broadcaster_user_login(), "streamer_1")
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
@@ -0,0 +1,142 @@
+use tape_drive::cassette::Recorder;
+use tape_drive::downloader::{self, FetchOptions, Strategy, TwitchUserData};
+use tape_drive::images::ImageCache;
+use tape_drive::mock::{Fault, Fixture, MockServer};
+use tape_drive::nom_parser::Streamer;
+
+mod common;
+use common::cache_dir;
+
+const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0xff];
+
+fn user(id: &str, profile_image_url: &str, offline_image_url: &str) -> serde_json::Value {
+    let mut user = common::user(id);
+    user["profile_image_url"] = profile_image_url.into();
+    user["offline_image_url"] = offline_image_url.into();
+    user
+}
+
+/// Users from a Helix mock whose images are served by `images`.
+async fn users(images: &MockServer) -> Vec<TwitchUserData> {
+    let url = images.url();
+    let fixture = Fixture::default()
+        .with_user(user(
+            "0",
+            &format!("{}/0/profile.png", url),
+            &format!("{}/0/offline.png", url),
+        ))
+        // the same image under another url
+        .with_user(user("1", &format!("{}/1/profile.png", url), ""));
+    let helix = MockServer::start(fixture).await.unwrap();
+    let streamers = [
+        Streamer::new("Streamer 0", "streamer_0", "rust"),
+        Streamer::new("Streamer 1", "streamer_1", "rust"),
+    ];
+    let options = FetchOptions::new().with_strategy(Strategy::Sequential);
+    downloader::fetch_users(&helix.config(), streamers.iter().collect(), &options)
+        .await
+        .unwrap()
+        .users
+}
+
+#[async_std::test]
+async fn caches_images_by_content() {
+    let dir = cache_dir("images_content");
+    let images = MockServer::start(
+        Fixture::default()
+            .with_file("/0/profile.png", PNG)
+            .with_file("/0/offline.png", "offline")
+            .with_file("/1/profile.png", PNG),
+    )
+    .await
+    .unwrap();
+    let users = users(&images).await;
+    let cache = ImageCache::new(&dir);
+
+    let first = cache.sync(&images.config(), &users).await.unwrap();
+    let second = cache.sync(&images.config(), &users).await.unwrap();
+    images.set_file("/0/offline.png", "new offline");
+    let third = cache.sync(&images.config(), &users).await.unwrap();
+
+    assert_eq!((first.downloaded, first.unchanged), (3, 0));
+    assert_eq!((second.downloaded, second.unchanged), (0, 3));
+    assert_eq!((third.downloaded, third.unchanged), (1, 2));
+    let manifest = cache.manifest();
+    let user_0 = manifest.user("0").unwrap();
+    let user_1 = manifest.user("1").unwrap();
+    assert_eq!(user_0.profile_image, user_1.profile_image);
+    assert_eq!(user_1.offline_image, None);
+    let profile_image = cache.path(user_0.profile_image.as_ref().unwrap());
+    assert_eq!(std::fs::read(profile_image).unwrap(), PNG);
+    let offline_image = cache.path(user_0.offline_image.as_ref().unwrap());
+    assert_eq!(std::fs::read(offline_image).unwrap(), b"new offline");
+    // the profile image once, both offline images
+    assert_eq!(std::fs::read_dir(dir.join("objects")).unwrap().count(), 3);
+    let mut statuses: Vec<u16> = images.requests().iter().map(|r| r.status).collect();
+    statuses.sort_unstable();
+    assert_eq!(statuses, [200, 200, 200, 200, 304, 304, 304, 304, 304]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[async_std::test]
+async fn keeps_previous_image_when_download_fails() {
+    let dir = cache_dir("images_failed");
+    let images = MockServer::start(
+        Fixture::default()
+            .with_file("/0/profile.png", PNG)
+            .with_file("/0/offline.png", "offline")
+            .with_file("/1/profile.png", PNG),
+    )
+    .await
+    .unwrap();
+    let users = users(&images).await;
+    let cache = ImageCache::new(&dir);
+    cache.sync(&images.config(), &users).await.unwrap();
+    images.inject("/0/offline.png", Fault::Status(404));
+
+    let sync = cache.sync(&images.config(), &users).await.unwrap();
+
+    assert_eq!(sync.failed.len(), 1);
+    assert_eq!(sync.failed[0].1.status(), Some(404));
+    let offline_image = sync.manifest.user("0").unwrap().offline_image.as_ref();
+    assert_eq!(
+        std::fs::read(cache.path(offline_image.unwrap())).unwrap(),
+        b"offline"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[async_std::test]
+async fn replays_recorded_images() {
+    let images = MockServer::start(
+        Fixture::default()
+            .with_file("/0/profile.png", PNG)
+            .with_file("/0/offline.png", "offline")
+            .with_file("/1/profile.png", PNG),
+    )
+    .await
+    .unwrap();
+    let users = users(&images).await;
+    let recorder = Recorder::new();
+    let config = images.config().with_recorder(recorder.clone());
+    let recorded_dir = cache_dir("images_recorded");
+    ImageCache::new(&recorded_dir)
+        .sync(&config, &users)
+        .await
+        .unwrap();
+    drop(images);
+
+    let dir = cache_dir("images_replayed");
+    let cache = ImageCache::new(&dir);
+    let config = downloader::Config::new("", "").with_replay(recorder.cassette());
+    let sync = cache.sync(&config, &users).await.unwrap();
+
+    assert_eq!((sync.downloaded, sync.failed.len()), (3, 0));
+    let profile_image = sync.manifest.user("0").unwrap().profile_image.as_ref();
+    assert_eq!(
+        std::fs::read(cache.path(profile_image.unwrap())).unwrap(),
+        PNG
+    );
+    std::fs::remove_dir_all(&recorded_dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
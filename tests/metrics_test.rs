@@ -0,0 +1,57 @@
+use tape_drive::downloader::{self, FetchOptions};
+use tape_drive::metrics::Metrics;
+use tape_drive::mock::{Fault, Fixture, MockServer};
+use tape_drive::nom_parser::Streamer;
+
+mod common;
+
+#[async_std::test]
+async fn counts_crawl_requests_and_logins() {
+    let fixture = Fixture::default().with_user(common::user("0"));
+    let server = MockServer::start(fixture).await.unwrap();
+    server.inject("/helix/users", Fault::RateLimited);
+    let metrics = Metrics::new();
+    let config = server.config().with_metrics(metrics.clone());
+    let streamers = [
+        Streamer::new("Streamer 0", "streamer_0", "rust"),
+        Streamer::new("Gone", "deleted_user", "go"),
+    ];
+
+    downloader::fetch_users(&config, streamers.iter().collect(), &FetchOptions::new())
+        .await
+        .unwrap();
+    let metrics_server = metrics.serve("127.0.0.1:0").await.unwrap();
+    let text = surf::get(metrics_server.url())
+        .await
+        .unwrap()
+        .body_string()
+        .await
+        .unwrap();
+
+    for line in [
+        "tape_drive_requests_total{endpoint=\"/helix/users\",status=\"200\"} 1",
+        "tape_drive_requests_total{endpoint=\"/helix/users\",status=\"429\"} 1",
+        "tape_drive_request_duration_seconds_count{endpoint=\"/helix/users\"} 2",
+        "tape_drive_rate_limit_waits_total 1",
+        "tape_drive_logins_total{result=\"matched\"} 1",
+        "tape_drive_logins_total{result=\"unmatched\"} 1",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{} missing in\n{}",
+            line,
+            text
+        );
+    }
+}
+
+#[test]
+fn writes_text_file() {
+    let path = std::env::temp_dir().join("tape_drive_metrics_test.prom");
+
+    Metrics::new().write_to(&path).unwrap();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("# TYPE tape_drive_requests_total counter"));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,6 +1,8 @@
+use std::error::Error as _;
 use std::fs::File;
 use std::io::prelude::*;
 use tape_drive::nom_parser;
+use tape_drive::Error;
 
 #[test]
 fn parse_file_test() {
@@ -177,8 +179,33 @@ fn parse_file_test() {
     ];
     //println!("{:?}", streamers);
     for name in streamer_names {
-        assert!(streamers.iter().any(|s| s.name() == &name), name);
+        assert!(streamers.iter().any(|s| s.name() == name), "{}", name);
     }
 
     assert_eq!(streamers.len(), 180 - 15);
 }
+
+#[test]
+fn parse_list_reports_skipped_blocks() {
+    let contents = std::fs::read_to_string("files/README.md").unwrap();
+
+    let list = nom_parser::parse_list(&contents).unwrap();
+
+    assert_eq!(list.streamers.len(), 180 - 15);
+    assert_eq!(list.skipped.len(), 15);
+    assert!(list.skipped.iter().any(|name| name == "Brad Garropy"));
+}
+
+#[test]
+fn parse_error_keeps_offset_and_source() {
+    let contents = "# Developers That Stream\n\n### Someone\nno content headline\n";
+
+    let e = nom_parser::parse_list(contents).unwrap_err();
+
+    match &e {
+        Error::Parse { offset, .. } => assert_eq!(*offset, Some(38)),
+        _ => panic!("not a parse error: {}", e),
+    }
+    assert!(e.to_string().contains("at byte 38"));
+    assert!(e.source().is_some());
+}
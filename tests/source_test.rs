@@ -0,0 +1,42 @@
+use tape_drive::cache::{Freshness, HttpCache};
+use tape_drive::downloader::Config;
+use tape_drive::mock::{Fixture, MockServer};
+use tape_drive::source::ListSource;
+
+#[async_std::test]
+async fn loads_local_file_with_checksum() {
+    let source: ListSource = "files/README.md".parse().unwrap();
+    let cache = HttpCache::new(std::env::temp_dir().join("tape_drive_source_test_unused"));
+
+    let list = source.load(&Config::new("", ""), &cache).await.unwrap();
+
+    assert!(list.content().starts_with("# Awesome Developer Streams"));
+    assert_eq!(
+        list.sha256(),
+        "7fb3e93fdf054c11330d9b9b60f428b7004abb9d3bf6ce75888bfb196ef22f66"
+    );
+    assert_eq!(list.freshness(), None);
+}
+
+#[async_std::test]
+async fn loads_url_through_cache() {
+    let dir = std::env::temp_dir().join("tape_drive_source_test_url");
+    let _ = std::fs::remove_dir_all(&dir);
+    let server = MockServer::start(Fixture::default().with_file("/list.md", "abc"))
+        .await
+        .unwrap();
+    let source: ListSource = format!("{}/list.md", server.url()).parse().unwrap();
+
+    let list = source
+        .load(&server.config(), &HttpCache::new(&dir))
+        .await
+        .unwrap();
+
+    assert_eq!(list.content(), "abc");
+    assert_eq!(
+        list.sha256(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(list.freshness(), Some(Freshness::Changed));
+    std::fs::remove_dir_all(&dir).unwrap();
+}